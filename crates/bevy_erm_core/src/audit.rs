@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use bevy_ecs::prelude::*;
+
+use crate::*;
+
+// What a write did to a component
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuditOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl AuditOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOperation::Insert => "insert",
+            AuditOperation::Update => "update",
+            AuditOperation::Delete => "delete",
+        }
+    }
+}
+
+// A write of a component as recorded in the audit table
+// The rows before and after the write are JSON objects of their columns, None when there is no row
+#[derive(Clone, PartialEq, Debug)]
+pub struct AuditEntry {
+    pub table: String,
+    pub entity: DatabaseEntityId,
+    pub request: RequestId,
+    pub operation: AuditOperation,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub changed_at: String,
+    pub actor: Option<String>,
+}
+
+// Inserting the resource records every write of a component in the audit table, in the
+// transaction of the request that made it so the entries are committed along with the changes
+// The actor of a request is given with set_actor, eg. by the system handling its event
+#[derive(Resource)]
+pub struct AuditLog {
+    table: String,
+    actors: RwLock<HashMap<RequestId, String>>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog::new("audit_log")
+    }
+}
+
+impl AuditLog {
+    pub fn new(table: &str) -> Self {
+        AuditLog {
+            table: table.to_string(),
+            actors: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    pub fn set_actor(&self, request: RequestId, actor: impl Into<String>) {
+        self.actors.write().unwrap().insert(request, actor.into());
+    }
+
+    pub fn actor(&self, request: RequestId) -> Option<String> {
+        self.actors.read().unwrap().get(&request).cloned()
+    }
+
+    // Forgets the actor once the request has been committed
+    pub fn finish_request(&self, request: RequestId) {
+        self.actors.write().unwrap().remove(&request);
+    }
+
+    pub fn table_schema(&self) -> TableSchema {
+        TableSchema {
+            name: self.table.clone(),
+            columns: vec![
+                ColumnSchema::new("id", "INTEGER", false),
+                ColumnSchema::new("table_name", "TEXT", false),
+                ColumnSchema::new("entity_id", "TEXT", false),
+                ColumnSchema::new("request", "TEXT", false),
+                ColumnSchema::new("operation", "TEXT", false),
+                ColumnSchema::new("before", "TEXT", true),
+                ColumnSchema::new("after", "TEXT", true),
+                ColumnSchema::new("changed_at", "TEXT", false),
+                ColumnSchema::new("actor", "TEXT", true),
+            ],
+            primary_key: vec!["id".to_string()],
+            foreign_keys: vec![],
+        }
+    }
+}
+
+// Reads the row of a component as a JSON object with `query`, which binds the key of the entity
+// Used by the generated component mappers
+pub async fn audit_row_json(
+    tr: &mut <SqlxSqliteDatabaseResource as DatabaseResource>::Transaction,
+    query: &str,
+    db_entity: &DatabaseEntityId,
+) -> Result<Option<String>, ()> {
+    let mut guard = tr.lock().await;
+    let tr = guard.a.as_mut().unwrap();
+
+    let mut query = sqlx::query_scalar::<_, String>(query);
+    for part in db_entity.parts() {
+        query = query.bind(part);
+    }
+    query.fetch_optional(&mut **tr).await.map_err(|_| ())
+}
+
+// Writes the entry to the audit table in the transaction of the request
+pub async fn insert_audit_entry(
+    tr: &mut <SqlxSqliteDatabaseResource as DatabaseResource>::Transaction,
+    log: &AuditLog,
+    entry: &AuditEntry,
+) -> Result<(), ()> {
+    let mut guard = tr.lock().await;
+    let tr = guard.a.as_mut().unwrap();
+
+    let insert_query = format!(
+        "INSERT INTO {} (table_name, entity_id, request, operation, before, after, changed_at, actor) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        log.table()
+    );
+    sqlx::query(&insert_query)
+        .bind(&entry.table)
+        .bind(entry.entity.to_string())
+        .bind(entry.request.to_string())
+        .bind(entry.operation.as_str())
+        .bind(&entry.before)
+        .bind(&entry.after)
+        .bind(&entry.changed_at)
+        .bind(&entry.actor)
+        .execute(&mut **tr)
+        .await
+        .map(|_| ())
+        .map_err(|_| ())
+}
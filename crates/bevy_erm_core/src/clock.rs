@@ -0,0 +1,91 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bevy_ecs::prelude::*;
+
+// The time written to #[created_at] and #[updated_at] fields when components are flushed
+// Tests can insert Clock::fixed to get the same timestamps on every run
+#[derive(Resource, Clone)]
+pub struct Clock {
+    now: Arc<dyn Fn() -> SystemTime + Send + Sync>,
+}
+
+impl Clock {
+    pub fn fixed(time: SystemTime) -> Self {
+        Clock {
+            now: Arc::new(move || time),
+        }
+    }
+
+    // A fixed time given in seconds since the unix epoch
+    pub fn fixed_at_secs(secs: u64) -> Self {
+        Clock::fixed(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn now(&self) -> SystemTime {
+        (self.now)()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock {
+            now: Arc::new(SystemTime::now),
+        }
+    }
+}
+
+// The types a #[created_at] or #[updated_at] field can have
+pub trait Timestamp {
+    fn from_system_time(time: SystemTime) -> Self;
+}
+
+// Seconds since the unix epoch
+impl Timestamp for i64 {
+    fn from_system_time(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        }
+    }
+}
+
+// "YYYY-MM-DD HH:MM:SS" in UTC, the format of SQLite's CURRENT_TIMESTAMP
+impl Timestamp for String {
+    fn from_system_time(time: SystemTime) -> Self {
+        let secs = i64::from_system_time(time);
+        let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+        // Converts days since the epoch to a date of the proleptic Gregorian calendar
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day % 3600 / 60,
+            secs_of_day % 60
+        )
+    }
+}
+
+impl<T: Timestamp> Timestamp for Option<T> {
+    fn from_system_time(time: SystemTime) -> Self {
+        Some(T::from_system_time(time))
+    }
+}
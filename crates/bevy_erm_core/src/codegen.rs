@@ -0,0 +1,302 @@
+use futures::executor::block_on;
+
+use crate::database_resource::SqlxSqliteDatabaseResource;
+use crate::migrations::MIGRATIONS_TABLE;
+
+// Every strict, reserved and weak keyword of Rust, which the generated names must not be
+const KEYWORDS: &[&str] = &[
+    "Self",
+    "abstract",
+    "as",
+    "async",
+    "await",
+    "become",
+    "box",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "do",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "final",
+    "fn",
+    "for",
+    "gen",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "macro",
+    "macro_rules",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "override",
+    "priv",
+    "pub",
+    "raw",
+    "ref",
+    "return",
+    "safe",
+    "self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "typeof",
+    "union",
+    "unsafe",
+    "unsized",
+    "use",
+    "virtual",
+    "where",
+    "while",
+    "yield",
+];
+
+// A column of an existing table as read from `PRAGMA table_info`
+#[derive(Clone, Debug, sqlx::FromRow)]
+struct ExistingColumn {
+    name: String,
+    #[sqlx(rename = "type")]
+    sql_type: String,
+    #[sqlx(rename = "notnull")]
+    not_null: bool,
+    // The position of the column in the primary key, 0 if it is not part of it
+    pk: i64,
+}
+
+impl ExistingColumn {
+    fn is_boolean(&self) -> bool {
+        let sql_type = self.sql_type.to_uppercase();
+        sql_type == "BOOLEAN" || sql_type == "BOOL"
+    }
+
+    fn rust_type(&self) -> String {
+        let sql_type = self.sql_type.to_uppercase();
+        let rust_type = if sql_type.contains("INT") {
+            "i64"
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|text| sql_type.contains(text))
+        {
+            "String"
+        } else if sql_type.is_empty() || sql_type.contains("BLOB") {
+            "Vec<u8>"
+        } else {
+            "f64"
+        };
+
+        if self.not_null || self.pk > 0 {
+            rust_type.to_string()
+        } else {
+            format!("Option<{}>", rust_type)
+        }
+    }
+}
+
+// users -> User, purchased_items -> PurchasedItem, countries -> Country
+fn struct_name(table: &str) -> String {
+    let singular = if let Some(stem) = table.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if table.ends_with("ss") {
+        table.to_string()
+    } else {
+        table.strip_suffix('s').unwrap_or(table).to_string()
+    };
+
+    type_name(&singular, "Table")
+}
+
+// A valid identifier for the name: characters that can not be part of one become underscores,
+// a leading digit is prefixed with one and a keyword is followed by one
+fn identifier(name: &str) -> String {
+    let mut identifier = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if identifier == "_" || KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+    identifier
+}
+
+// is_admin -> IsAdmin, and with the kind of type in front of names that are not valid types
+// self -> MarkerSelf, 2fa -> Marker2fa
+fn type_name(name: &str, kind: &str) -> String {
+    let name = pascal_case(&identifier(name));
+    if name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || KEYWORDS.contains(&name.as_str())
+    {
+        format!("{}{}", kind, name)
+    } else {
+        name
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+// The field for a column, with the attributes needed when the column name is not a valid field name
+fn field(column: &ExistingColumn, main_key: bool) -> String {
+    let mut field = String::new();
+    if main_key {
+        field.push_str("    #[main_key]\n");
+    }
+
+    let name = identifier(&column.name.to_lowercase());
+    if name != column.name {
+        field.push_str(&format!("    #[column = {:?}]\n", column.name));
+    }
+
+    field.push_str(&format!("    pub {}: {},\n", name, column.rust_type()));
+    field
+}
+
+const DERIVES: &str = "#[derive(Component, Debug, Default, Clone, DBQueryDerive)]";
+
+// Generates the component structs for a table
+// Boolean columns become marker components and the remaining columns the fields of one component
+fn table_components(table: &str, columns: &[ExistingColumn]) -> String {
+    let mut key_columns = columns
+        .iter()
+        .filter(|column| column.pk > 0)
+        .collect::<Vec<_>>();
+    key_columns.sort_by_key(|column| column.pk);
+
+    let mut attributes = vec![format!("#[table_name = \"{}\"]", table)];
+    // Text keys are never generated so a single text key column is a #[main_key] field the key of
+    // a new component is taken from, rather than a #[primary_key] attribute
+    let mut key_attributes = Vec::new();
+    let mut key_fields = key_columns.len() > 1;
+    match key_columns.as_slice() {
+        [] => {
+            attributes
+                .push("// TODO: the table has no primary key so the rowid is used".to_string());
+            attributes.push("#[primary_key = \"rowid\"]".to_string());
+        }
+        [key] => {
+            if key.name != "id" {
+                key_attributes.push(format!("#[primary_key = \"{}\"]", key.name));
+            }
+            match key.rust_type().as_str() {
+                "String" => {
+                    attributes.push("#[key_type = \"text\"]".to_string());
+                    key_fields = true;
+                }
+                "Vec<u8>" => attributes.push("#[key_type = \"uuid\"]".to_string()),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+    let marker_attributes = [attributes.clone(), key_attributes.clone()]
+        .concat()
+        .join("\n");
+    if !key_fields {
+        attributes.extend(key_attributes);
+    }
+    let attributes = attributes.join("\n");
+
+    // A single integer or uuid key column is not a field, other keys are made of #[main_key] fields
+    let fields = columns
+        .iter()
+        .filter(|column| !column.is_boolean() && (key_fields || column.pk == 0))
+        .map(|column| field(column, key_fields && column.pk > 0))
+        .collect::<String>();
+
+    let mut components = Vec::new();
+    if fields.is_empty() {
+        components.push(format!(
+            "// TODO: {} has no columns other than its key and markers\n",
+            table
+        ));
+    } else {
+        components.push(format!(
+            "{}\n{}\npub struct {} {{\n{}}}\n",
+            DERIVES,
+            attributes,
+            struct_name(table),
+            fields
+        ));
+    }
+
+    for marker in columns.iter().filter(|column| column.is_boolean()) {
+        let name = type_name(&marker.name, "Marker");
+        let column = if name.to_lowercase() == marker.name {
+            String::new()
+        } else {
+            format!("#[column = {:?}]\n", marker.name)
+        };
+        components.push(format!(
+            "{}\n{}\n{}pub struct {} {{}}\n",
+            DERIVES, marker_attributes, column, name
+        ));
+    }
+
+    components.join("\n")
+}
+
+// Generates the components for every table of an existing database as rust source
+pub fn generate_components(db: &SqlxSqliteDatabaseResource) -> Result<String, sqlx::Error> {
+    let pool = db.pool();
+
+    block_on(async {
+        let tables = sqlx::query_scalar::<_, String>(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != ? ORDER BY name",
+        )
+        .bind(MIGRATIONS_TABLE)
+        .fetch_all(&pool)
+        .await?;
+
+        let mut source = String::from(
+            "use async_trait::async_trait;\n\
+             use bevy_ecs::component::Component;\n\
+             use bevy_erm::*;\n\
+             use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};\n",
+        );
+
+        for table in tables {
+            let columns = sqlx::query_as::<_, ExistingColumn>(
+                "SELECT name, type, \"notnull\", pk FROM pragma_table_info(?) ORDER BY cid",
+            )
+            .bind(&table)
+            .fetch_all(&pool)
+            .await?;
+
+            source.push('\n');
+            source.push_str(&table_components(&table, &columns));
+        }
+
+        Ok(source)
+    })
+}
@@ -3,6 +3,11 @@ use std::fmt::Display;
 use bevy_ecs::component::Component;
 use bevy_ecs::prelude::*;
 use bevy_mod_index::prelude::*;
+use bevy_utils::all_tuples;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type, ValueRef};
 
 use crate::DatabaseEntityWithRequest;
 
@@ -29,17 +34,154 @@ impl From<Persisted> for bool {
     }
 }
 
-#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Debug, Default, Hash, sqlx::Type)]
-#[sqlx(transparent)]
-pub struct DatabaseEntityId(pub i64);
+// The type of the values stored in the key column of a table
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum DatabaseKeyType {
+    #[default]
+    Integer,
+    Text,
+    #[cfg(feature = "uuid")]
+    Uuid,
+}
+
+// The key of a row in the database
+// Tables keyed by more than one column have a composite key with a key for each column
+#[derive(Clone, Eq, Ord, PartialEq, PartialOrd, Debug, Hash)]
+pub enum DatabaseEntityId {
+    Integer(i64),
+    Text(String),
+    #[cfg(feature = "uuid")]
+    Uuid(uuid::Uuid),
+    Composite(Vec<DatabaseEntityId>),
+}
+
+impl DatabaseEntityId {
+    // The keys of each key column in the order they are declared
+    pub fn parts(&self) -> &[DatabaseEntityId] {
+        match self {
+            DatabaseEntityId::Composite(parts) => parts,
+            _ => std::slice::from_ref(self),
+        }
+    }
+}
+
+impl Default for DatabaseEntityId {
+    fn default() -> Self {
+        DatabaseEntityId::Integer(0)
+    }
+}
+
+impl From<i64> for DatabaseEntityId {
+    fn from(id: i64) -> Self {
+        DatabaseEntityId::Integer(id)
+    }
+}
+
+impl From<String> for DatabaseEntityId {
+    fn from(id: String) -> Self {
+        DatabaseEntityId::Text(id)
+    }
+}
+
+impl From<&str> for DatabaseEntityId {
+    fn from(id: &str) -> Self {
+        DatabaseEntityId::Text(id.to_string())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for DatabaseEntityId {
+    fn from(id: uuid::Uuid) -> Self {
+        DatabaseEntityId::Uuid(id)
+    }
+}
+
+macro_rules! composite_key_from_tuple {
+    ( $( ($name:ident, $var:ident) ),* ) => {
+        impl<$($name: Into<DatabaseEntityId>),*> From<($($name,)*)> for DatabaseEntityId {
+            fn from(($($var,)*): ($($name,)*)) -> Self {
+                DatabaseEntityId::Composite(vec![$($var.into()),*])
+            }
+        }
+    };
+}
+
+all_tuples!(composite_key_from_tuple, 2, 6, K, k);
 
 impl Display for DatabaseEntityId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            DatabaseEntityId::Integer(id) => write!(f, "{}", id),
+            DatabaseEntityId::Text(id) => write!(f, "{}", id),
+            #[cfg(feature = "uuid")]
+            DatabaseEntityId::Uuid(id) => write!(f, "{}", id),
+            DatabaseEntityId::Composite(parts) => {
+                let parts = parts
+                    .iter()
+                    .map(|part| part.to_string())
+                    .collect::<Vec<String>>();
+                write!(f, "({})", parts.join(", "))
+            }
+        }
     }
 }
 
-#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Component, Debug)]
+impl Type<Sqlite> for DatabaseEntityId {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        let compatible =
+            <i64 as Type<Sqlite>>::compatible(ty) || <String as Type<Sqlite>>::compatible(ty);
+
+        #[cfg(feature = "uuid")]
+        let compatible = compatible || <uuid::Uuid as Type<Sqlite>>::compatible(ty);
+
+        compatible
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for DatabaseEntityId {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        match self {
+            DatabaseEntityId::Integer(id) => <i64 as Encode<Sqlite>>::encode_by_ref(id, buf),
+            DatabaseEntityId::Text(id) => <String as Encode<Sqlite>>::encode_by_ref(id, buf),
+            #[cfg(feature = "uuid")]
+            DatabaseEntityId::Uuid(id) => <uuid::Uuid as Encode<Sqlite>>::encode_by_ref(id, buf),
+            DatabaseEntityId::Composite(_) => {
+                panic!("Composite keys must be bound one key column at a time using parts()")
+            }
+        }
+    }
+}
+
+// The key is decoded based on the type of the value stored in the column
+impl<'r> Decode<'r, Sqlite> for DatabaseEntityId {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let ty = value.type_info();
+
+        if <i64 as Type<Sqlite>>::compatible(&ty) {
+            return Ok(DatabaseEntityId::Integer(<i64 as Decode<Sqlite>>::decode(
+                value,
+            )?));
+        }
+
+        // Uuids are stored as blobs, uuids stored as text are read as text keys
+        #[cfg(feature = "uuid")]
+        if sqlx::TypeInfo::name(ty.as_ref()) == "BLOB" {
+            return Ok(DatabaseEntityId::Uuid(
+                <uuid::Uuid as Decode<Sqlite>>::decode(value)?,
+            ));
+        }
+
+        Ok(DatabaseEntityId::Text(<String as Decode<Sqlite>>::decode(
+            value,
+        )?))
+    }
+}
+
+#[derive(Clone, Eq, Ord, PartialEq, PartialOrd, Component, Debug)]
 pub struct DatabaseEntity {
     pub id: DatabaseEntityId,
 
@@ -51,6 +193,9 @@ pub struct DatabaseEntity {
 
     pub dirty: bool,
 
+    // Whether the entity has been deleted and should be removed from the database when flushed
+    pub deleted: bool,
+
     // The request the database entity belongs to
     pub request: RequestId,
 }
@@ -59,8 +204,8 @@ impl DatabaseEntityWithRequest for DatabaseEntity {
     fn request(&self) -> &RequestId {
         &self.request
     }
-    fn id(&self) -> &DatabaseEntityId {
-        &self.id
+    fn id(&self) -> DatabaseEntityId {
+        self.id.clone()
     }
 }
 
@@ -73,7 +218,7 @@ impl IndexInfo for DatabaseEntityIndex {
     type Storage = NoStorage<Self>;
 
     fn value(c: &Self::Component) -> Self::Value {
-        c.id
+        c.id.clone()
     }
 }
 
@@ -92,23 +237,42 @@ pub struct FlushEvent {
     pub request: RequestId,
 }
 
-impl DatabaseEntityWithRequest for (DatabaseEntityId, RequestId) {
+// Allows any key, including tuples of keys for composite keys, to be used with a request
+impl<K: Into<DatabaseEntityId> + Clone + Sync + Send> DatabaseEntityWithRequest for (K, RequestId) {
     fn request(&self) -> &RequestId {
         &self.1
     }
 
-    fn id(&self) -> &DatabaseEntityId {
-        &self.0
+    fn id(&self) -> DatabaseEntityId {
+        self.0.clone().into()
     }
 }
 
-impl DatabaseEntityWithRequest for (RequestId, DatabaseEntityId) {
+impl<K: Into<DatabaseEntityId> + Clone + Sync + Send> DatabaseEntityWithRequest for (RequestId, K) {
     fn request(&self) -> &RequestId {
         &self.0
     }
 
-    fn id(&self) -> &DatabaseEntityId {
-        &self.1
+    fn id(&self) -> DatabaseEntityId {
+        self.1.clone().into()
+    }
+}
+
+// Includes the rows of #[soft_delete] components that have been deleted, eg.
+// users.get(&WithDeleted((1, request))) or UserQueryLoadAll(request).with_deleted()
+pub struct WithDeleted<T>(pub T);
+
+impl<D: DatabaseEntityWithRequest> DatabaseEntityWithRequest for WithDeleted<D> {
+    fn request(&self) -> &RequestId {
+        self.0.request()
+    }
+
+    fn id(&self) -> DatabaseEntityId {
+        self.0.id()
+    }
+
+    fn includes_deleted(&self) -> bool {
+        true
     }
 }
 
@@ -1,5 +1,6 @@
 use crate::*;
 use async_trait::async_trait;
+use std::time::SystemTime;
 
 use bevy_ecs::query::{QueryItem, ROQueryItem, WorldQuery};
 use bevy_ecs::{
@@ -7,8 +8,7 @@ use bevy_ecs::{
     world::unsafe_world_cell::UnsafeWorldCell,
 };
 use bevy_mod_index::prelude::*;
-use bevy_utils::hashbrown::HashSet;
-use casey::lower;
+use bevy_utils::{all_tuples, hashbrown::HashSet};
 
 #[async_trait]
 pub trait CustomDatabaseQuery<DbResource: DatabaseResource, DerefItem: Send>: Sync {
@@ -45,16 +45,17 @@ pub trait DBQueryInfo<DbResource: DatabaseResource> {
     type ReadOnlyItem<'a>: Send + From<ReadOnlyItem<'a, Self, DbResource>>;
     type Item<'a>: Send;
 
+    // None when the entity does not have the components, errors are only for failed reads
     async fn get<'w, D: DatabaseEntityWithRequest>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::ReadOnlyItem<'w>, ()>;
+    ) -> Result<Option<Self::ReadOnlyItem<'w>>, ()>;
     async fn get_mut<'w, D: DatabaseEntityWithRequest>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::Item<'w>, ()>;
+    ) -> Result<Option<Self::Item<'w>>, ()>;
     async fn update_component<'w>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
@@ -67,6 +68,12 @@ pub trait DBQueryInfo<DbResource: DatabaseResource> {
         db_entity: &DatabaseEntity,
         component: Self::ReadOnlyItem<'w>,
     ) -> Result<(), ()>;
+    async fn delete_component<'w>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'w>,
+        db_entity: &DatabaseEntity,
+        component: Self::ReadOnlyItem<'w>,
+    ) -> Result<(), ()>;
     async fn load_components<'w, R: ReturnSelector<'w>, CDQ>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
@@ -81,6 +88,108 @@ pub trait DBQueryInfo<DbResource: DatabaseResource> {
         component: Self::DerefItem,
         request: RequestId,
     ) -> Result<(), ()>;
+    // Writes the component in the transaction of the request straight away, updating the row
+    // when one already has the key, so the entity does not need to be loaded first
+    async fn upsert<D: DatabaseEntityWithRequest>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        component: Self::DerefItem,
+        db_entity: &D,
+    ) -> Result<(), ()>;
+    // Adds components that have already been read from the database to the entity
+    // Components already in memory are kept as they may have been modified by the request
+    fn insert_loaded_component(
+        world: UnsafeWorldCell<'_>,
+        entity: Entity,
+        component: Self::DerefItem,
+    );
+    // Sets the timestamp fields of the components of the entity from the Clock resource
+    // before they are inserted, or updated when `inserted` is false
+    fn set_timestamps(world: UnsafeWorldCell<'_>, entity: Entity, inserted: bool);
+    // Writes the components removed from the entities of the request, eg. clearing a marker
+    async fn remove_components(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        request: RequestId,
+    ) -> Result<(), ()>;
+}
+
+// Looks up the entity in the world that has been loaded for the database entity
+pub fn find_entity(world: UnsafeWorldCell<'_>, db_entity_id: DatabaseEntityId) -> Option<Entity> {
+    let mut reader = IntoSystem::into_system(
+        move |mut index: Index<DatabaseEntityIndex>| -> HashSet<Entity> {
+            index.lookup(&db_entity_id)
+        },
+    );
+
+    let entity_set: HashSet<Entity> = unsafe {
+        reader.initialize(world.world_mut());
+        reader.run((), world.world_mut())
+    };
+
+    entity_set.into_iter().next()
+}
+
+// The time of the Clock resource, or the system time when there is none
+pub fn clock_now(world: UnsafeWorldCell<'_>) -> SystemTime {
+    unsafe { world.get_resource::<Clock>() }
+        .map(Clock::now)
+        .unwrap_or_else(SystemTime::now)
+}
+
+// Finds the entity for the database entity, spawning an entity without any components
+// if it has not been read into memory yet
+pub fn get_or_spawn_entity<D: DatabaseEntityWithRequest>(
+    world: UnsafeWorldCell<'_>,
+    db_entity: &D,
+) -> Entity {
+    match find_entity(world, db_entity.id()) {
+        Some(entity) => entity,
+        None => unsafe {
+            world
+                .world_mut()
+                .spawn(DatabaseEntity {
+                    id: db_entity.id(),
+                    persisted: true.into(),
+                    dirty: false,
+                    deleted: false,
+                    request: *db_entity.request(),
+                })
+                .id()
+        },
+    }
+}
+
+// Loads the components for a DBQueryInfo composed of other DBQueryInfos (eg. tuples and options)
+// The custom query returns all of the components for an entity at once
+pub async fn load_composed_components<'w, Q, R, CDQ, DbResource>(
+    db: &DbResource,
+    world: UnsafeWorldCell<'w>,
+    request: RequestId,
+    get_comp_from_db: CDQ,
+) -> Result<Vec<<R as ReturnSelector<'w>>::ReturnItem>, ()>
+where
+    Q: DBQueryInfo<DbResource>,
+    R: ReturnSelector<'w>,
+    CDQ: CustomDatabaseQuery<DbResource, Q::DerefItem> + Send,
+    DbResource: DatabaseResource,
+{
+    let components = {
+        let tr = db.get_transaction(request);
+
+        get_comp_from_db.query(tr).await?
+    };
+
+    let entities = components
+        .into_iter()
+        .map(|(db_entity, component)| {
+            let entity = get_or_spawn_entity(world, &db_entity);
+            Q::insert_loaded_component(world, entity, component);
+            entity
+        })
+        .collect();
+
+    Ok(R::load_components_from_entities(world, entities))
 }
 
 pub struct QueryFetchState<'w, 's, DbResource: DatabaseResource> {
@@ -147,7 +256,11 @@ where
 
 pub trait DatabaseEntityWithRequest: Sync + Send {
     fn request(&self) -> &RequestId;
-    fn id(&self) -> &DatabaseEntityId;
+    fn id(&self) -> DatabaseEntityId;
+    // Whether rows of #[soft_delete] components that have been deleted are loaded, see WithDeleted
+    fn includes_deleted(&self) -> bool {
+        false
+    }
 }
 
 impl<'w, 's, Q: DBQueryInfo<DbResource>, DbResource: DatabaseResource>
@@ -157,14 +270,18 @@ impl<'w, 's, Q: DBQueryInfo<DbResource>, DbResource: DatabaseResource>
         &self,
         db_entity: &D,
     ) -> Result<Q::ReadOnlyItem<'w>, ()> {
-        Q::get(self.db.as_ref(), self.world, db_entity).await
+        Q::get(self.db.as_ref(), self.world, db_entity)
+            .await?
+            .ok_or(())
     }
 
     pub async fn get_mut<D: DatabaseEntityWithRequest>(
         &self,
         db_entity: &D,
     ) -> Result<Q::Item<'w>, ()> {
-        Q::get_mut(self.db.as_ref(), self.world, db_entity).await
+        Q::get_mut(self.db.as_ref(), self.world, db_entity)
+            .await?
+            .ok_or(())
     }
 
     pub async fn update_component(
@@ -198,7 +315,46 @@ impl<'w, 's, Q: DBQueryInfo<DbResource>, DbResource: DatabaseResource>
         Q::create(self.db.as_ref(), self.world, component, request).await
     }
 
+    // Inserts the component with the key, or updates the row that already has it, without
+    // loading it first
+    pub async fn upsert<K: Into<DatabaseEntityId>>(
+        &self,
+        component: Q::DerefItem,
+        id: K,
+        request: RequestId,
+    ) -> Result<(), ()> {
+        Q::upsert(
+            self.db.as_ref(),
+            self.world,
+            component,
+            &(id.into(), request),
+        )
+        .await
+    }
+
+    // Marks the entity as deleted, its components are removed from the database when flushed
+    pub async fn delete<D: DatabaseEntityWithRequest>(&self, db_entity: &D) -> Result<(), ()> {
+        // The components need to be in memory to know which tables to delete from
+        Q::get(self.db.as_ref(), self.world, db_entity)
+            .await?
+            .ok_or(())?;
+
+        let entity = find_entity(self.world, db_entity.id()).ok_or(())?;
+        unsafe {
+            self.world
+                .get_entity(entity)
+                .unwrap()
+                .get_mut::<DatabaseEntity>()
+                .unwrap()
+                .deleted = true;
+        }
+
+        Ok(())
+    }
+
     pub async fn update_or_insert_component(&self, entity: Entity) -> Result<(), ()> {
+        self.set_timestamps(entity);
+
         unsafe {
             let mut q = self
                 .world
@@ -206,7 +362,13 @@ impl<'w, 's, Q: DBQueryInfo<DbResource>, DbResource: DatabaseResource>
                 .query::<(&DatabaseEntity, Q::WorldQuery<'w>)>();
             let (db_entity, comp) = q.get(self.world.world(), entity).unwrap();
 
-            if db_entity.persisted.into() {
+            if db_entity.deleted {
+                if db_entity.persisted.into() {
+                    Q::delete_component(self.db.as_ref(), self.world, db_entity, comp.into()).await
+                } else {
+                    Ok(())
+                }
+            } else if db_entity.persisted.into() {
                 if db_entity.dirty {
                     Q::update_component(self.db.as_ref(), self.world, db_entity, comp.into()).await
                 } else {
@@ -218,6 +380,30 @@ impl<'w, 's, Q: DBQueryInfo<DbResource>, DbResource: DatabaseResource>
         }
     }
 
+    // Sets the timestamp fields of the components of the entity when it is about to be written
+    pub fn set_timestamps(&self, entity: Entity) {
+        let db_entity = unsafe {
+            self.world
+                .get_entity(entity)
+                .and_then(|e| e.get::<DatabaseEntity>())
+        };
+        let Some(db_entity) = db_entity else {
+            return;
+        };
+        if db_entity.deleted {
+            return;
+        }
+
+        let inserted = !bool::from(db_entity.persisted);
+        if inserted || db_entity.dirty {
+            Q::set_timestamps(self.world, entity, inserted);
+        }
+    }
+
+    pub async fn remove_components(&self, request: RequestId) -> Result<(), ()> {
+        Q::remove_components(self.db.as_ref(), self.world, request).await
+    }
+
     pub fn commit(&self, request: RequestId) -> Result<(), ()> {
         self.db.commit_transaction(request);
 
@@ -230,10 +416,11 @@ pub trait ComponentMapper {
     type Component;
     type Executor: Send;
 
+    // None when the entity does not have the component
     async fn get<'c>(
         e: &mut Self::Executor,
         db_entity: &DatabaseEntityId,
-    ) -> Result<Self::Component, ()>;
+    ) -> Result<Option<Self::Component>, ()>;
 
     async fn update_component<'c>(
         tr: &mut Self::Executor,
@@ -246,52 +433,103 @@ pub trait ComponentMapper {
         db_entity: &DatabaseEntityId,
         component: &Self::Component,
     ) -> Result<(), ()>;
-}
 
-#[derive(Component)]
-pub struct NullComponent();
+    // `now` is the time from the Clock, written to the column of #[soft_delete] components
+    async fn delete_component<'c>(
+        tr: &mut Self::Executor,
+        db_entity: &DatabaseEntityId,
+        component: &Self::Component,
+        now: SystemTime,
+    ) -> Result<(), ()>;
 
-impl RequestIdIndexInfo for NullComponent {}
+    // Like get but also finds rows that have been soft deleted
+    // The same as get for components without #[soft_delete]
+    async fn get_with_deleted<'c>(
+        e: &mut Self::Executor,
+        db_entity: &DatabaseEntityId,
+    ) -> Result<Option<Self::Component>, ()>;
 
-// To satisfy the type system when a DBQueryInfo is composed of other DBQueryInfos
-impl IndexInfo for NullComponent {
-    type Component = NullComponent;
-    type Value = RequestId;
-    type Storage = NoStorage<Self>;
+    // Inserts the component, or updates its row when one with the key already exists
+    async fn upsert_component<'c>(
+        tr: &mut Self::Executor,
+        db_entity: &DatabaseEntityId,
+        component: &Self::Component,
+    ) -> Result<(), ()>;
 
-    fn value(_c: &Self::Component) -> Self::Value {
-        RequestId(generational_arena::Index::from_raw_parts(0, 0))
+    // Inserts the new components of a table together, as the flush pipeline does
+    // Components with their own row insert them in multi-row statements, others one at a time
+    async fn insert_components<'c>(
+        tr: &mut Self::Executor,
+        components: &[(&DatabaseEntityId, &Self::Component)],
+    ) -> Result<(), ()>
+    where
+        Self::Component: Sync,
+    {
+        for (db_entity, component) in components {
+            Self::insert_component(tr, db_entity, component).await?;
+        }
+        Ok(())
     }
-}
 
-pub struct NullMapper;
-#[async_trait]
-impl ComponentMapper for NullMapper {
-    type Component = NullComponent;
-    type Executor = ();
-
-    async fn get<'c>(
-        _e: &mut Self::Executor,
-        _db_entity: &DatabaseEntityId,
-    ) -> Result<Self::Component, ()> {
-        unimplemented!()
+    // Updates the changed components of a table together, as the flush pipeline does
+    async fn update_components<'c>(
+        tr: &mut Self::Executor,
+        components: &[(&DatabaseEntityId, &Self::Component)],
+    ) -> Result<(), ()>
+    where
+        Self::Component: Sync,
+    {
+        for (db_entity, component) in components {
+            Self::update_component(tr, db_entity, component).await?;
+        }
+        Ok(())
     }
 
-    async fn update_component<'c>(
-        _tr: &mut Self::Executor,
-        _db_entity: &DatabaseEntityId,
-        _component: &Self::Component,
-    ) -> Result<(), ()> {
-        unimplemented!()
-    }
+    // Reads the row of the component as a JSON object of its columns for the audit log
+    async fn row_json<'c>(
+        tr: &mut Self::Executor,
+        db_entity: &DatabaseEntityId,
+    ) -> Result<Option<String>, ()>;
 
-    async fn insert_component<'c>(
-        _tr: &mut Self::Executor,
-        _db_entity: &DatabaseEntityId,
-        _component: &Self::Component,
-    ) -> Result<(), ()> {
-        unimplemented!()
+    // Writes an entry to the audit table in the transaction, see AuditLog
+    async fn write_audit_entry<'c>(
+        tr: &mut Self::Executor,
+        log: &AuditLog,
+        entry: &AuditEntry,
+    ) -> Result<(), ()>;
+
+    // Whether the component has #[created_at] or #[updated_at] fields to set before writing
+    const TIMESTAMPED: bool = false;
+
+    // Sets the #[created_at] and #[updated_at] fields before the component is inserted
+    fn set_created_at(_component: &mut Self::Component, _now: SystemTime) {}
+
+    // Sets the #[updated_at] fields before the component is updated
+    fn set_updated_at(_component: &mut Self::Component, _now: SystemTime) {}
+
+    // Called when the component has been removed from a persisted entity
+    // The row is kept, only components stored in a single column (markers, enums) are cleared
+    async fn remove_component<'c>(
+        tr: &mut Self::Executor,
+        db_entity: &DatabaseEntityId,
+    ) -> Result<(), ()>;
+
+    // The type of the key column used when the database resource issues a new key
+    const KEY_TYPE: DatabaseKeyType = DatabaseKeyType::Integer;
+
+    // The key of a newly created component
+    // Components keyed by their own fields (eg. composite keys) provide it,
+    // otherwise a new key is issued by the database resource
+    fn key(_component: &Self::Component) -> Option<DatabaseEntityId> {
+        None
     }
+
+    // Whether inserting the component inserts its row, rather than setting the columns of a row
+    // inserted by another component of the table, as markers and enum components do
+    const INSERTS_ROW: bool = true;
+
+    // The columns of the table the component is stored in
+    fn table_schema() -> TableSchema;
 }
 
 // Used to help speicfy whether the returned component is read only or mutable
@@ -327,16 +565,17 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::ReadOnlyItem<'w>, ()> {
-        T::get(db, world, db_entity).await.map(Some)
+    ) -> Result<Option<Self::ReadOnlyItem<'w>>, ()> {
+        // A component the entity does not have, eg. a marker that is not set, is None
+        Ok(Some(T::get(db, world, db_entity).await?))
     }
 
     async fn get_mut<'w, D: DatabaseEntityWithRequest>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::Item<'w>, ()> {
-        T::get_mut(db, world, db_entity).await.map(Some)
+    ) -> Result<Option<Self::Item<'w>>, ()> {
+        Ok(Some(T::get_mut(db, world, db_entity).await?))
     }
 
     async fn update_component<'w>(
@@ -365,16 +604,30 @@ where
         }
     }
 
+    async fn delete_component<'w>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'w>,
+        db_entity: &DatabaseEntity,
+        component: Self::ReadOnlyItem<'w>,
+    ) -> Result<(), ()> {
+        if let Some(component) = component {
+            T::delete_component(db, world, db_entity, component).await
+        } else {
+            Ok(())
+        }
+    }
+
     async fn load_components<'w, R: ReturnSelector<'w>, CDQ>(
-        _db: &DbResource,
-        _world: UnsafeWorldCell<'w>,
-        _request: RequestId,
-        _get_comp_from_db: CDQ,
+        db: &DbResource,
+        world: UnsafeWorldCell<'w>,
+        request: RequestId,
+        get_comp_from_db: CDQ,
     ) -> Result<Vec<<R as ReturnSelector<'w>>::ReturnItem>, ()>
     where
         CDQ: CustomDatabaseQuery<DbResource, Self::DerefItem> + Send,
     {
-        Err(())
+        load_composed_components::<Self, R, CDQ, DbResource>(db, world, request, get_comp_from_db)
+            .await
     }
 
     async fn create(
@@ -389,6 +642,41 @@ where
             Ok(())
         }
     }
+
+    async fn upsert<D: DatabaseEntityWithRequest>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        component: Self::DerefItem,
+        db_entity: &D,
+    ) -> Result<(), ()> {
+        if let Some(component) = component {
+            T::upsert(db, world, component, db_entity).await
+        } else {
+            Ok(())
+        }
+    }
+
+    fn insert_loaded_component(
+        world: UnsafeWorldCell<'_>,
+        entity: Entity,
+        component: Self::DerefItem,
+    ) {
+        if let Some(component) = component {
+            T::insert_loaded_component(world, entity, component);
+        }
+    }
+
+    fn set_timestamps(world: UnsafeWorldCell<'_>, entity: Entity, inserted: bool) {
+        T::set_timestamps(world, entity, inserted);
+    }
+
+    async fn remove_components(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        request: RequestId,
+    ) -> Result<(), ()> {
+        T::remove_components(db, world, request).await
+    }
 }
 
 impl<'a, T: ComponentMapper, DbResource: DatabaseResource<Transaction = T::Executor>>
@@ -423,7 +711,7 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::ReadOnlyItem<'w>, ()> {
+    ) -> Result<Option<Self::ReadOnlyItem<'w>>, ()> {
         SingleComponentRetriever::<T, DbResource>::get(db, world, db_entity).await
     }
 
@@ -431,7 +719,7 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::Item<'w>, ()> {
+    ) -> Result<Option<Self::Item<'w>>, ()> {
         SingleComponentRetriever::<T, DbResource>::get(db, world, db_entity).await
     }
 
@@ -455,6 +743,16 @@ where
             .await
     }
 
+    async fn delete_component<'w>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'w>,
+        db_entity: &DatabaseEntity,
+        component: Self::ReadOnlyItem<'w>,
+    ) -> Result<(), ()> {
+        SingleComponentRetriever::<T, DbResource>::delete_component(db, world, db_entity, component)
+            .await
+    }
+
     async fn load_components<'w, R: ReturnSelector<'w>, CDQ>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
@@ -481,6 +779,35 @@ where
     ) -> Result<(), ()> {
         SingleComponentRetriever::<T, DbResource>::create(db, world, component, request).await
     }
+
+    async fn upsert<D: DatabaseEntityWithRequest>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        component: Self::DerefItem,
+        db_entity: &D,
+    ) -> Result<(), ()> {
+        SingleComponentRetriever::<T, DbResource>::upsert(db, world, component, db_entity).await
+    }
+
+    fn insert_loaded_component(
+        world: UnsafeWorldCell<'_>,
+        entity: Entity,
+        component: Self::DerefItem,
+    ) {
+        SingleComponentRetriever::<T, DbResource>::insert_loaded_component(world, entity, component)
+    }
+
+    fn set_timestamps(world: UnsafeWorldCell<'_>, entity: Entity, inserted: bool) {
+        SingleComponentRetriever::<T, DbResource>::set_timestamps(world, entity, inserted)
+    }
+
+    async fn remove_components(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        request: RequestId,
+    ) -> Result<(), ()> {
+        SingleComponentRetriever::<T, DbResource>::remove_components(db, world, request).await
+    }
 }
 
 impl<'b, T: ComponentMapper, DbResource: DatabaseResource<Transaction = T::Executor>>
@@ -508,7 +835,7 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::ReadOnlyItem<'w>, ()> {
+    ) -> Result<Option<Self::ReadOnlyItem<'w>>, ()> {
         SingleComponentRetriever::<T, DbResource>::get(db, world, db_entity).await
     }
 
@@ -516,7 +843,7 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::Item<'w>, ()> {
+    ) -> Result<Option<Self::Item<'w>>, ()> {
         SingleComponentRetriever::<T, DbResource>::get_mut(db, world, db_entity).await
     }
 
@@ -540,6 +867,16 @@ where
             .await
     }
 
+    async fn delete_component<'w>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'w>,
+        db_entity: &DatabaseEntity,
+        component: Self::ReadOnlyItem<'w>,
+    ) -> Result<(), ()> {
+        SingleComponentRetriever::<T, DbResource>::delete_component(db, world, db_entity, component)
+            .await
+    }
+
     async fn load_components<'w, R: ReturnSelector<'w>, CDQ>(
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
@@ -566,6 +903,35 @@ where
     ) -> Result<(), ()> {
         SingleComponentRetriever::<T, DbResource>::create(db, world, component, request).await
     }
+
+    async fn upsert<D: DatabaseEntityWithRequest>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        component: Self::DerefItem,
+        db_entity: &D,
+    ) -> Result<(), ()> {
+        SingleComponentRetriever::<T, DbResource>::upsert(db, world, component, db_entity).await
+    }
+
+    fn insert_loaded_component(
+        world: UnsafeWorldCell<'_>,
+        entity: Entity,
+        component: Self::DerefItem,
+    ) {
+        SingleComponentRetriever::<T, DbResource>::insert_loaded_component(world, entity, component)
+    }
+
+    fn set_timestamps(world: UnsafeWorldCell<'_>, entity: Entity, inserted: bool) {
+        SingleComponentRetriever::<T, DbResource>::set_timestamps(world, entity, inserted)
+    }
+
+    async fn remove_components(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        request: RequestId,
+    ) -> Result<(), ()> {
+        SingleComponentRetriever::<T, DbResource>::remove_components(db, world, request).await
+    }
 }
 
 /*
@@ -574,7 +940,7 @@ where
  a new DBQueryInfo that returns a tuple of the items
 */
 macro_rules! simple_composition_of_db_queries {
-    ( $( $name:ident )* ) => {
+    ( $( ($name:ident, $var:ident) ),* ) => {
         // include this Head Z so that can specify that the rest of them have the same database
         // looks quite ugly but it works
         #[async_trait]
@@ -592,73 +958,116 @@ macro_rules! simple_composition_of_db_queries {
                         )>
         {
             type DerefItem = (Z::DerefItem, $($name::DerefItem,)*);
-            // type Mapper = NullMapper;
             type WorldQuery<'a> = (Z::WorldQuery<'a>, $($name::WorldQuery<'a>,)*);
 
             type ReadOnlyItem<'a> = (Z::ReadOnlyItem<'a>, $($name::ReadOnlyItem<'a>,)*);
 
             type Item<'a> = (Z::Item<'a>, $($name::Item<'a>,)*);
 
-            async fn get<'w, DbEntity: DatabaseEntityWithRequest>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DbEntity) -> Result<Self::ReadOnlyItem<'w>, ()> {
-            //returns a tuple of all the gets
-                Ok((
-                    Z::get(db, world, db_entity).await?,
+            async fn get<'w, DbEntity: DatabaseEntityWithRequest>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DbEntity) -> Result<Option<Self::ReadOnlyItem<'w>>, ()> {
+            //returns a tuple of all the gets, None when the entity is missing any of them
+                Ok(Some((
+                    match Z::get(db, world, db_entity).await? {
+                        Some(z) => z,
+                        None => return Ok(None),
+                    },
                     $(
-                    {
-                        $name::get(db, world, db_entity).await?
+                    match $name::get(db, world, db_entity).await? {
+                        Some($var) => $var,
+                        None => return Ok(None),
                     },
-                )*))
+                )*)))
             }
 
-            async fn get_mut<'w, DbEntity: DatabaseEntityWithRequest>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DbEntity) -> Result<Self::Item<'w>, ()> {
-                //returns a tuple of all the gets
-                Ok((
-                    Z::get_mut(db, world, db_entity).await?,
-
+            async fn get_mut<'w, DbEntity: DatabaseEntityWithRequest>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DbEntity) -> Result<Option<Self::Item<'w>>, ()> {
+                //returns a tuple of all the gets, None when the entity is missing any of them
+                Ok(Some((
+                    match Z::get_mut(db, world, db_entity).await? {
+                        Some(z) => z,
+                        None => return Ok(None),
+                    },
                     $(
-                    {
-                        $name::get_mut(db, world, db_entity).await?
+                    match $name::get_mut(db, world, db_entity).await? {
+                        Some($var) => $var,
+                        None => return Ok(None),
                     },
-                )*))
+                )*)))
             }
 
             async fn update_component<'w>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DatabaseEntity, component: Self::ReadOnlyItem<'w>) -> Result<(), ()> {
 
-                let (z, $(lower!($name),)*) = component;
+                let (z, $($var,)*) = component;
 
                 Z::update_component(db, world, db_entity, z).await?;
-                $($name::update_component(db, world, db_entity, lower!($name)).await?;)*
+                $($name::update_component(db, world, db_entity, $var).await?;)*
 
                 Ok(())
             }
 
             async fn insert_component<'w>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DatabaseEntity, component: Self::ReadOnlyItem<'w>) -> Result<(), ()> {
-                let (z, $(lower!($name),)*) = component;
+                let (z, $($var,)*) = component;
 
                 Z::insert_component(db, world, db_entity, z).await?;
-                $($name::insert_component(db, world, db_entity, lower!($name)).await?;)*
+                $($name::insert_component(db, world, db_entity, $var).await?;)*
 
                 Ok(())
             }
 
-            // CODE SMELL: probably should split up interface to avoid this method
+            async fn delete_component<'w>(db: &DbResource, world: UnsafeWorldCell<'w>, db_entity: &DatabaseEntity, component: Self::ReadOnlyItem<'w>) -> Result<(), ()> {
+                let (z, $($var,)*) = component;
+
+                Z::delete_component(db, world, db_entity, z).await?;
+                $($name::delete_component(db, world, db_entity, $var).await?;)*
+
+                Ok(())
+            }
+
+            // The custom query must return all of the components in the tuple for each entity
             async fn load_components<'w, R : ReturnSelector<'w>, CDQ>(
-                _db: &DbResource,
-                _world: UnsafeWorldCell<'w>,
-                _request: RequestId,
-                _get_comp_from_db: CDQ
+                db: &DbResource,
+                world: UnsafeWorldCell<'w>,
+                request: RequestId,
+                get_comp_from_db: CDQ
             ) -> Result<Vec<<R as ReturnSelector<'w>>::ReturnItem>, ()>
             where
                 CDQ: CustomDatabaseQuery<DbResource, Self::DerefItem> + Send
             {
-                unimplemented!()
+                load_composed_components::<Self, R, CDQ, DbResource>(db, world, request, get_comp_from_db).await
             }
 
             async fn create(db: &DbResource, world: UnsafeWorldCell<'_>, component: Self::DerefItem, request: RequestId) -> Result<(), ()> {
-                let (z, $(lower!($name),)*) = component;
+                let (z, $($var,)*) = component;
 
                 Z::create(db, world, z, request).await?;
-                $($name::create(db, world, lower!($name), request).await?;)*
+                $($name::create(db, world, $var, request).await?;)*
+
+                Ok(())
+            }
+
+            async fn upsert<D: DatabaseEntityWithRequest>(db: &DbResource, world: UnsafeWorldCell<'_>, component: Self::DerefItem, db_entity: &D) -> Result<(), ()> {
+                let (z, $($var,)*) = component;
+
+                Z::upsert(db, world, z, db_entity).await?;
+                $($name::upsert(db, world, $var, db_entity).await?;)*
+
+                Ok(())
+            }
+
+            fn insert_loaded_component(world: UnsafeWorldCell<'_>, entity: Entity, component: Self::DerefItem) {
+                let (z, $($var,)*) = component;
+
+                Z::insert_loaded_component(world, entity, z);
+                $($name::insert_loaded_component(world, entity, $var);)*
+            }
+
+            fn set_timestamps(world: UnsafeWorldCell<'_>, entity: Entity, inserted: bool) {
+                Z::set_timestamps(world, entity, inserted);
+                $($name::set_timestamps(world, entity, inserted);)*
+            }
+
+            async fn remove_components(db: &DbResource, world: UnsafeWorldCell<'_>, request: RequestId) -> Result<(), ()> {
+                Z::remove_components(db, world, request).await?;
+                $($name::remove_components(db, world, request).await?;)*
 
                 Ok(())
             }
@@ -666,21 +1075,44 @@ macro_rules! simple_composition_of_db_queries {
     }
 }
 
-// Create a simple composition of DBQueryInfo for tuples of length 1 to 10
+// Create a simple composition of DBQueryInfo for tuples of length 2 to 12
 // Allows DBQueryInfo to be composed of other DBQueryInfo
 // eg. DBQuery<(User, Item)>
-// simple_composition_of_db_queries!{}
-simple_composition_of_db_queries! {A}
-simple_composition_of_db_queries! {A B}
-simple_composition_of_db_queries! {A B C}
-simple_composition_of_db_queries! {A B C D}
-// simple_composition_of_db_queries!{A B C D E}
-// simple_composition_of_db_queries!{A B C D E F}
-// simple_composition_of_db_queries!{A B C D E F G}
-// simple_composition_of_db_queries!{A B C D E F G H}
-// simple_composition_of_db_queries!{A B C D E F G H I}
-// simple_composition_of_db_queries!{A B C D E F G H I J}
-// simple_composition_of_db_queries!{A B C D E F G H I J K}
+// The head of the tuple is always Z so the macro is invoked with the remaining 1 to 11 elements
+all_tuples!(simple_composition_of_db_queries, 1, 11, A, a);
+
+// Runs the write of a component, recording it in the AuditLog with the row before and after it
+// when there is one and the write changed the row, eg. removing a component keeping its row does not
+// A macro as the future of a generic async fn would need the mapper to outlive the trait methods
+macro_rules! audited_write {
+    ($mapper:ty, $world:expr, $tr:expr, $db_entity:expr, $request:expr, $operation:expr, $write:expr) => {{
+        let log = unsafe { $world.get_resource::<AuditLog>() };
+        let before = match log {
+            Some(_) => Some(<$mapper>::row_json($tr, $db_entity).await?),
+            None => None,
+        };
+        $write.await?;
+        match (log, before) {
+            (Some(log), Some(before)) => {
+                let after = <$mapper>::row_json($tr, $db_entity).await?;
+                if after != before {
+                    let entry = Self::audit_entry(
+                        $world,
+                        log,
+                        $db_entity,
+                        $request,
+                        $operation,
+                        (before, after),
+                    );
+                    <$mapper>::write_audit_entry($tr, log, &entry).await
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }};
+}
 
 #[derive(Default)]
 pub struct SingleComponentRetriever<Mapper, DbResource> {
@@ -695,28 +1127,53 @@ impl<
 where
     <MyMapper as ComponentMapper>::Component: Component,
 {
+    // The entry recording a write, an insert that found a row already there, eg. an upsert,
+    // is recorded as an update
+    fn audit_entry(
+        world: UnsafeWorldCell<'_>,
+        log: &AuditLog,
+        db_entity: &DatabaseEntityId,
+        request: RequestId,
+        operation: AuditOperation,
+        (before, after): (Option<String>, Option<String>),
+    ) -> AuditEntry {
+        let operation = match (operation, &before) {
+            (AuditOperation::Insert, Some(_)) if MyMapper::INSERTS_ROW => AuditOperation::Update,
+            _ => operation,
+        };
+        AuditEntry {
+            table: MyMapper::table_schema().name,
+            entity: db_entity.clone(),
+            request,
+            operation,
+            before,
+            after,
+            changed_at: String::from_system_time(clock_now(world)),
+            actor: log.actor(request),
+        }
+    }
+
+    async fn get_from_db<D: DatabaseEntityWithRequest>(
+        tr: &mut DbResource::Transaction,
+        db_entity: &D,
+    ) -> Result<Option<MyMapper::Component>, ()> {
+        if db_entity.includes_deleted() {
+            MyMapper::get_with_deleted(tr, &db_entity.id()).await
+        } else {
+            MyMapper::get(tr, &db_entity.id()).await
+        }
+    }
+
+    // None when the component is not in memory and the database does not have it for the entity
     pub async fn get_internal<D: DatabaseEntityWithRequest>(
         db: &DbResource,
         world: UnsafeWorldCell<'_>,
         db_entity: &D,
         component_preloaded: Option<<MyMapper as ComponentMapper>::Component>,
-    ) -> Entity {
+    ) -> Result<Option<Entity>, ()> {
         let mut tr = db.get_transaction(*db_entity.request());
 
-        let db_entity_id = *db_entity.id();
-
-        let mut reader = IntoSystem::into_system(
-            move |mut index: Index<DatabaseEntityIndex>| -> HashSet<Entity> {
-                index.lookup(&db_entity_id)
-            },
-        );
-
-        let entity_set: HashSet<Entity> = unsafe {
-            reader.initialize(world.world_mut());
-            reader.run((), world.world_mut())
-        };
-
-        match entity_set.iter().next() {
+        match find_entity(world, db_entity.id()).as_ref() {
             // Entity has been read into memory before
             Some(entity) => {
                 match unsafe {
@@ -725,18 +1182,21 @@ where
                         .get::<<MyMapper as ComponentMapper>::Component>(*entity)
                 } {
                     // Entity also already has the desired component
-                    Some(_) => *entity,
+                    Some(_) => Ok(Some(*entity)),
                     // Entity does not have the desired component (Load from database)
                     None => {
                         let db_component = match component_preloaded {
                             Some(component) => component,
-                            None => MyMapper::get(&mut tr, db_entity.id()).await.unwrap(),
+                            None => match Self::get_from_db(&mut tr, db_entity).await? {
+                                Some(component) => component,
+                                None => return Ok(None),
+                            },
                         };
                         // write the component to the entity
                         unsafe {
                             let w = world.world_mut();
                             w.entity_mut(*entity).insert(db_component);
-                            *entity
+                            Ok(Some(*entity))
                         }
                     }
                 }
@@ -745,22 +1205,26 @@ where
             None => {
                 let component = match component_preloaded {
                     Some(component) => component,
-                    None => MyMapper::get(&mut tr, db_entity.id()).await.unwrap(),
+                    None => match Self::get_from_db(&mut tr, db_entity).await? {
+                        Some(component) => component,
+                        None => return Ok(None),
+                    },
                 };
                 unsafe {
                     let w = world.world_mut();
                     let entity = w
                         .spawn((
                             DatabaseEntity {
-                                id: *db_entity.id(),
+                                id: db_entity.id(),
                                 persisted: true.into(),
                                 dirty: false,
+                                deleted: false,
                                 request: *db_entity.request(),
                             },
                             component,
                         ))
                         .id();
-                    entity
+                    Ok(Some(entity))
                 }
             }
         }
@@ -784,7 +1248,11 @@ where
         let mut entities = vec![];
 
         for (db_entity, component) in components {
-            entities.push(Self::get_internal(db, world, &db_entity, Some(component)).await);
+            // Always found as the component has been read already
+            if let Some(entity) = Self::get_internal(db, world, &db_entity, Some(component)).await?
+            {
+                entities.push(entity);
+            }
         }
 
         Ok(entities)
@@ -796,7 +1264,13 @@ where
         db_entity: &DatabaseEntity,
         component: &MyMapper::Component,
     ) -> Result<(), ()> {
-        if db_entity.persisted.into() {
+        if db_entity.deleted {
+            if db_entity.persisted.into() {
+                Self::delete_component(db, world, db_entity, component).await
+            } else {
+                Ok(())
+            }
+        } else if db_entity.persisted.into() {
             if db_entity.dirty {
                 Self::update_component(db, world, db_entity, component).await
             } else {
@@ -844,14 +1318,15 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::ReadOnlyItem<'w>, ()> {
-        let entity = Self::get_internal(db, world, db_entity, None).await;
+    ) -> Result<Option<Self::ReadOnlyItem<'w>>, ()> {
+        let Some(entity) = Self::get_internal(db, world, db_entity, None).await? else {
+            return Ok(None);
+        };
 
         unsafe {
             Ok(world
                 .world()
-                .get::<<MyMapper as ComponentMapper>::Component>(entity)
-                .unwrap())
+                .get::<<MyMapper as ComponentMapper>::Component>(entity))
         }
     }
 
@@ -859,8 +1334,10 @@ where
         db: &DbResource,
         world: UnsafeWorldCell<'w>,
         db_entity: &D,
-    ) -> Result<Self::Item<'w>, ()> {
-        let entity = Self::get_internal(db, world, db_entity, None).await;
+    ) -> Result<Option<Self::Item<'w>>, ()> {
+        let Some(entity) = Self::get_internal(db, world, db_entity, None).await? else {
+            return Ok(None);
+        };
 
         unsafe {
             // set entity to dirty
@@ -874,30 +1351,65 @@ where
             Ok(world
                 .get_entity(entity)
                 .unwrap()
-                .get_mut::<<MyMapper as ComponentMapper>::Component>()
-                .unwrap())
+                .get_mut::<<MyMapper as ComponentMapper>::Component>())
         }
     }
 
     async fn update_component<'w>(
         db: &DbResource,
-        _world: UnsafeWorldCell<'w>,
+        world: UnsafeWorldCell<'w>,
         db_entity: &DatabaseEntity,
         component: ReadOnlyItem<'w, Self, DbResource>,
     ) -> Result<(), ()> {
         let mut tr = db.get_transaction(*db_entity.request());
 
-        MyMapper::update_component(&mut tr, db_entity.id(), component).await
+        audited_write!(
+            MyMapper,
+            world,
+            &mut tr,
+            &db_entity.id,
+            db_entity.request,
+            AuditOperation::Update,
+            MyMapper::update_component(&mut tr, &db_entity.id, component)
+        )
     }
 
     async fn insert_component<'w>(
         db: &DbResource,
-        _world: UnsafeWorldCell<'w>,
+        world: UnsafeWorldCell<'w>,
+        db_entity: &DatabaseEntity,
+        component: ReadOnlyItem<'w, Self, DbResource>,
+    ) -> Result<(), ()> {
+        let mut tr = db.get_transaction(*db_entity.request());
+
+        audited_write!(
+            MyMapper,
+            world,
+            &mut tr,
+            &db_entity.id,
+            db_entity.request,
+            AuditOperation::Insert,
+            MyMapper::insert_component(&mut tr, &db_entity.id, component)
+        )
+    }
+
+    async fn delete_component<'w>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'w>,
         db_entity: &DatabaseEntity,
         component: ReadOnlyItem<'w, Self, DbResource>,
     ) -> Result<(), ()> {
         let mut tr = db.get_transaction(*db_entity.request());
-        MyMapper::insert_component(&mut tr, db_entity.id(), component).await
+
+        audited_write!(
+            MyMapper,
+            world,
+            &mut tr,
+            &db_entity.id,
+            db_entity.request,
+            AuditOperation::Delete,
+            MyMapper::delete_component(&mut tr, &db_entity.id, component, clock_now(world))
+        )
     }
 
     async fn load_components<'w, R: ReturnSelector<'w>, CDQ>(
@@ -924,12 +1436,18 @@ where
     ) -> Result<(), ()> {
         unsafe {
             let w = world.world_mut();
+            // Components with text keys have to hold their key, eg. in a #[main_key] field
+            let id = match MyMapper::key(&component) {
+                Some(id) => id,
+                None => db.get_key(MyMapper::KEY_TYPE).ok_or(())?,
+            };
             w.spawn((
                 component,
                 DatabaseEntity {
-                    id: db.get_key(),
+                    id,
                     persisted: false.into(),
                     dirty: false,
+                    deleted: false,
                     request,
                 },
             ));
@@ -937,4 +1455,134 @@ where
 
         Ok(())
     }
+
+    async fn upsert<D: DatabaseEntityWithRequest>(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        component: Self::DerefItem,
+        db_entity: &D,
+    ) -> Result<(), ()> {
+        // The insert sets every timestamp, an existing row keeps its #[created_at] columns
+        let mut component = component;
+        MyMapper::set_created_at(&mut component, clock_now(world));
+
+        let mut tr = db.get_transaction(*db_entity.request());
+        let id = db_entity.id();
+        audited_write!(
+            MyMapper,
+            world,
+            &mut tr,
+            &id,
+            *db_entity.request(),
+            AuditOperation::Insert,
+            MyMapper::upsert_component(&mut tr, &id, &component)
+        )?;
+
+        // The row is written so the entity is persisted and only needs writing again when changed
+        unsafe {
+            let w = world.world_mut();
+            match find_entity(world, db_entity.id()) {
+                Some(entity) => {
+                    w.entity_mut(entity).insert(component);
+                }
+                None => {
+                    w.spawn((
+                        component,
+                        DatabaseEntity {
+                            id: db_entity.id(),
+                            persisted: true.into(),
+                            dirty: false,
+                            deleted: false,
+                            request: *db_entity.request(),
+                        },
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_loaded_component(
+        world: UnsafeWorldCell<'_>,
+        entity: Entity,
+        component: Self::DerefItem,
+    ) {
+        unsafe {
+            let mut entity = world.world_mut().entity_mut(entity);
+            if !entity.contains::<MyMapper::Component>() {
+                entity.insert(component);
+            }
+        }
+    }
+
+    fn set_timestamps(world: UnsafeWorldCell<'_>, entity: Entity, inserted: bool) {
+        // Components without timestamps are not marked as changed
+        if !MyMapper::TIMESTAMPED {
+            return;
+        }
+
+        let now = clock_now(world);
+        let component = unsafe {
+            world
+                .get_entity(entity)
+                .and_then(|entity| entity.get_mut::<MyMapper::Component>())
+        };
+        if let Some(mut component) = component {
+            if inserted {
+                MyMapper::set_created_at(&mut component, now);
+            } else {
+                MyMapper::set_updated_at(&mut component, now);
+            }
+        }
+    }
+
+    // The removals recorded in RemovedDatabaseComponents along with those of the current update,
+    // as Bevy only keeps removals for the update they were made in and the next one
+    async fn remove_components(
+        db: &DbResource,
+        world: UnsafeWorldCell<'_>,
+        request: RequestId,
+    ) -> Result<(), ()> {
+        let mut removed = unsafe { world.get_resource_mut::<RemovedDatabaseComponents>() }
+            .map(|mut records| records.take::<MyMapper::Component>(request))
+            .unwrap_or_default();
+        removed.extend(unsafe { world.world().removed::<MyMapper::Component>() });
+        removed.sort();
+        removed.dedup();
+
+        for entity in removed {
+            let db_entity = unsafe {
+                let Some(entity) = world.get_entity(entity) else {
+                    continue;
+                };
+                // The component has been added back since
+                if entity.contains::<MyMapper::Component>() {
+                    continue;
+                }
+                match entity.get::<DatabaseEntity>() {
+                    Some(db_entity) => db_entity,
+                    None => continue,
+                }
+            };
+
+            if db_entity.request != request || db_entity.deleted || !bool::from(db_entity.persisted)
+            {
+                continue;
+            }
+
+            let mut tr = db.get_transaction(request);
+            audited_write!(
+                MyMapper,
+                world,
+                &mut tr,
+                &db_entity.id,
+                request,
+                AuditOperation::Delete,
+                MyMapper::remove_component(&mut tr, &db_entity.id)
+            )?;
+        }
+
+        Ok(())
+    }
 }
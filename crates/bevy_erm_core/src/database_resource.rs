@@ -1,5 +1,6 @@
 use std::{
     any::TypeId,
+    collections::HashMap,
     sync::{Arc, RwLock},
 };
 
@@ -11,6 +12,7 @@ use futures::executor::block_on;
 use generational_arena::Arena;
 use sqlx::Transaction;
 
+use crate::audit::AuditLog;
 use crate::database_entity::*;
 use crate::database_query::*;
 
@@ -22,8 +24,9 @@ use crate::database_query::*;
 pub trait DatabaseResource: Resource + Default {
     type Transaction: Send;
 
-    // A way to get a unique key for the database
-    fn get_key(&self) -> DatabaseEntityId;
+    // A way to get a unique key for the database of the given key type
+    // None when keys of the type are not generated, so have to come from the component
+    fn get_key(&self, key_type: DatabaseKeyType) -> Option<DatabaseEntityId>;
     fn start_new_transaction(&self) -> RequestId;
     fn try_start_new_transaction(&self) -> Option<RequestId>;
     fn get_transaction(&self, request: RequestId) -> Self::Transaction;
@@ -48,21 +51,32 @@ pub struct SqlxSqliteDatabaseResource {
 
 impl Default for SqlxSqliteDatabaseResource {
     fn default() -> Self {
-        let pool = RwLock::new(
-            block_on(
-                sqlx::pool::PoolOptions::new()
-                    .min_connections(3)
-                    .connect("sqlite::memory:"),
-            )
-            .unwrap(),
-        );
+        SqlxSqliteDatabaseResource::connect("sqlite::memory:").unwrap()
+    }
+}
+
+impl SqlxSqliteDatabaseResource {
+    // Connects to the database at the url, eg. `sqlite:app.db?mode=rwc` to create a file database
+    // Insert the resource before adding the EntityRelationMapperPlugin to use it instead of the
+    // default in memory database
+    pub fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let pool = RwLock::new(block_on(
+            sqlx::pool::PoolOptions::new()
+                .min_connections(3)
+                .connect(url),
+        )?);
         let tr = RwLock::new(Arena::new());
         let db = DatabaseHandle {
             pool,
             tr,
             min_key: RwLock::new(0),
         };
-        SqlxSqliteDatabaseResource { db }
+        Ok(SqlxSqliteDatabaseResource { db })
+    }
+
+    // The pool the request transactions are started from
+    pub fn pool(&self) -> sqlx::SqlitePool {
+        self.db.pool.read().unwrap().clone()
     }
 }
 
@@ -85,10 +99,18 @@ impl DatabaseResource for SqlxSqliteDatabaseResource {
     // Rather than actually querying the database for key just hold on to the last key we had to issue
     // This is a bit of a hack but it's fine for now. As POC and only considering one machine
     // It is progressing into the negatives so that instantiating any objects with positive keys will not conflict
-    fn get_key(&self) -> DatabaseEntityId {
-        let mut min_key = self.db.min_key.write().unwrap();
-        *min_key -= 1;
-        DatabaseEntityId(*min_key)
+    // Uuid keys are randomly generated, text keys are natural keys so are never generated
+    fn get_key(&self, key_type: DatabaseKeyType) -> Option<DatabaseEntityId> {
+        match key_type {
+            DatabaseKeyType::Integer => {
+                let mut min_key = self.db.min_key.write().unwrap();
+                *min_key -= 1;
+                Some(DatabaseEntityId::Integer(*min_key))
+            }
+            DatabaseKeyType::Text => None,
+            #[cfg(feature = "uuid")]
+            DatabaseKeyType::Uuid => Some(DatabaseEntityId::Uuid(uuid::Uuid::new_v4())),
+        }
     }
 
     fn start_new_transaction(&self) -> RequestId {
@@ -138,6 +160,9 @@ pub trait ComponentMapperMapper {
     ) -> Result<(), ()>;
 }
 
+// Writes and commits each flushed request, for apps listing their components instead of registering them
+// The entities are written in no particular order, unlike the flush pipeline which writes referenced
+// rows first, so it only suits tables without foreign keys between them
 pub fn flush_component_to_db<
     'w1,
     'w2,
@@ -148,6 +173,7 @@ pub fn flush_component_to_db<
     mut flush_events: EventReader<FlushEvent>,
     mut index: Index<RequestIdIndex>,
     db_query: DatabaseQuery<DBQ, DbResource>,
+    audit_log: Option<Res<AuditLog>>,
 ) where
     'w1: 'w2,
     's: 'w2,
@@ -156,7 +182,127 @@ pub fn flush_component_to_db<
         for entity in index.lookup(&flush_event.request) {
             block_on(db_query.update_or_insert_component(entity)).unwrap();
         }
+        block_on(db_query.remove_components(flush_event.request)).unwrap();
 
         db_query.commit(flush_event.request).unwrap();
+        if let Some(audit_log) = &audit_log {
+            audit_log.finish_request(flush_event.request);
+        }
+    }
+}
+
+// The entities each request removed a component from, kept until the request is flushed as Bevy
+// only keeps removals until the end of the update after the one they were made in
+#[derive(Resource, Default)]
+pub struct RemovedDatabaseComponents {
+    removed: HashMap<(TypeId, RequestId), Vec<Entity>>,
+}
+
+impl RemovedDatabaseComponents {
+    pub fn record<C: Component>(&mut self, request: RequestId, entity: Entity) {
+        self.removed
+            .entry((TypeId::of::<C>(), request))
+            .or_default()
+            .push(entity);
+    }
+
+    // The entities the request removed the component from since it was last taken
+    pub fn take<C: Component>(&mut self, request: RequestId) -> Vec<Entity> {
+        self.removed
+            .remove(&(TypeId::of::<C>(), request))
+            .unwrap_or_default()
+    }
+}
+
+// Records the removals of the component from database entities under their request, so a request
+// flushed in a later update than the removal still writes it
+pub fn record_removed_components<C: Component>(
+    mut removed: RemovedComponents<C>,
+    db_entities: Query<&DatabaseEntity>,
+    mut records: ResMut<RemovedDatabaseComponents>,
+) {
+    for entity in removed.read() {
+        if let Ok(db_entity) = db_entities.get(entity) {
+            records.record::<C>(db_entity.request, entity);
+        }
+    }
+}
+
+// The flush pipeline inserts and updates rows with parents first, then deletes them children first,
+// so foreign keys hold throughout
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FlushPhase {
+    // Inserts and updates the components of entities that are not deleted
+    Write,
+    // Deletes the components of deleted entities and writes the removal of components
+    Delete,
+}
+
+// Writes the changes a request made to one component without committing them, inserting the
+// new rows and updating the changed rows of the table together
+// The flush pipeline runs this for each registered component, then commits once all are written
+pub fn write_component_to_db<C, DbResource: DatabaseResource>(
+    In((request, phase)): In<(RequestId, FlushPhase)>,
+    mut index: Index<RequestIdIndex>,
+    components: Query<(&DatabaseEntity, Option<&C>)>,
+    db: Res<DbResource>,
+    db_query: DatabaseQuery<Option<&C>, DbResource>,
+    audit_log: Option<Res<AuditLog>>,
+) -> Result<(), ()>
+where
+    C: ComponentMapper<Component = C, Executor = DbResource::Transaction> + Component,
+{
+    let entities = index.lookup(&request);
+
+    if phase == FlushPhase::Delete {
+        for entity in entities {
+            if components.get(entity).map_err(|_| ())?.0.deleted {
+                block_on(db_query.update_or_insert_component(entity))?;
+            }
+        }
+        return block_on(db_query.remove_components(request));
+    }
+
+    // Audited writes read the row before and after each one so are not batched
+    if audit_log.is_some() {
+        for entity in entities {
+            if !components.get(entity).map_err(|_| ())?.0.deleted {
+                block_on(db_query.update_or_insert_component(entity))?;
+            }
+        }
+        return Ok(());
     }
+
+    // Timestamps are set before the components are borrowed to be written
+    for entity in entities.iter() {
+        db_query.set_timestamps(*entity);
+    }
+
+    let mut inserted = Vec::new();
+    let mut updated = Vec::new();
+    for entity in entities {
+        let (db_entity, component) = components.get(entity).map_err(|_| ())?;
+        let Some(component) = component else {
+            continue;
+        };
+        if db_entity.deleted {
+            continue;
+        }
+        if !bool::from(db_entity.persisted) {
+            inserted.push((&db_entity.id, component));
+        } else if db_entity.dirty {
+            updated.push((&db_entity.id, component));
+        }
+    }
+
+    let mut tr = db.get_transaction(request);
+    block_on(async {
+        if !inserted.is_empty() {
+            C::insert_components(&mut tr, &inserted).await?;
+        }
+        if !updated.is_empty() {
+            C::update_components(&mut tr, &updated).await?;
+        }
+        Ok(())
+    })
 }
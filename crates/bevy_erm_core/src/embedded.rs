@@ -0,0 +1,33 @@
+use sqlx::query::Query;
+use sqlx::sqlite::{SqliteArguments, SqliteRow};
+use sqlx::Sqlite;
+
+use crate::schema::ColumnSchema;
+
+pub type SqliteQuery<'q> = Query<'q, Sqlite, SqliteArguments<'q>>;
+
+// A value object, eg. Money { amount, currency }, whose fields are stored in columns of the table
+// of the component it is embedded in with #[flatten(prefix = "...")]
+// Implemented with #[derive(DBEmbedded)]
+pub trait Embedded: Sized {
+    // The columns of the fields, each starting with the prefix
+    fn columns(prefix: &str) -> Vec<String>;
+
+    fn column_schemas(prefix: &str) -> Vec<ColumnSchema>;
+
+    fn from_row(row: &SqliteRow, prefix: &str) -> Result<Self, sqlx::Error>;
+
+    // Binds each field in the order of `columns`
+    fn bind<'q>(&self, query: SqliteQuery<'q>) -> SqliteQuery<'q>;
+}
+
+// Lets the generated queries bind an embedded value in the middle of a chain of binds
+pub trait BindEmbedded {
+    fn bind_embedded<E: Embedded>(self, value: &E) -> Self;
+}
+
+impl BindEmbedded for SqliteQuery<'_> {
+    fn bind_embedded<E: Embedded>(self, value: &E) -> Self {
+        value.bind(self)
+    }
+}
@@ -0,0 +1,153 @@
+use sqlx::encode::IsNull;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo};
+use sqlx::{Encode, Sqlite, Type};
+
+// A value compared against a column in a filter
+#[derive(Clone, PartialEq, Debug)]
+pub enum FilterValue {
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Bool(bool),
+}
+
+macro_rules! filter_value_from {
+    ($variant:ident, $($ty:ty),*) => {
+        $(
+            impl From<$ty> for FilterValue {
+                fn from(value: $ty) -> Self {
+                    FilterValue::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+filter_value_from!(Integer, i8, i16, i32, i64, u8, u16, u32);
+filter_value_from!(Real, f32, f64);
+filter_value_from!(Text, String, &str);
+filter_value_from!(Bool, bool);
+
+impl Type<Sqlite> for FilterValue {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for FilterValue {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        match self {
+            FilterValue::Integer(value) => <i64 as Encode<Sqlite>>::encode_by_ref(value, buf),
+            FilterValue::Real(value) => <f64 as Encode<Sqlite>>::encode_by_ref(value, buf),
+            FilterValue::Text(value) => <String as Encode<Sqlite>>::encode_by_ref(value, buf),
+            FilterValue::Bool(value) => <bool as Encode<Sqlite>>::encode_by_ref(value, buf),
+        }
+    }
+}
+
+// A condition added to the WHERE clause of a generated load query, eg.
+// Filter::json_path("details", "$.color").eq("red").and(Filter::column("quantity").gt(1))
+// Values and JSON paths are bound as parameters rather than written into the sql
+#[derive(Clone, PartialEq, Debug)]
+pub struct Filter {
+    sql: String,
+    values: Vec<FilterValue>,
+}
+
+// The left hand side of a comparison, made into a Filter by comparing it with a value
+#[derive(Clone, PartialEq, Debug)]
+pub struct FilterTerm {
+    sql: String,
+    values: Vec<FilterValue>,
+}
+
+impl Filter {
+    pub fn column(column: &str) -> FilterTerm {
+        FilterTerm {
+            sql: column.to_string(),
+            values: Vec::new(),
+        }
+    }
+
+    // The value at a path of the JSON stored in a column, eg. "$.address.city"
+    pub fn json_path(column: &str, path: &str) -> FilterTerm {
+        FilterTerm {
+            sql: format!("json_extract({}, ?)", column),
+            values: vec![path.into()],
+        }
+    }
+
+    pub fn and(self, other: Filter) -> Filter {
+        self.combine("AND", other)
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        self.combine("OR", other)
+    }
+
+    fn combine(mut self, operator: &str, other: Filter) -> Filter {
+        self.values.extend(other.values);
+        Filter {
+            sql: format!("({}) {} ({})", self.sql, operator, other.sql),
+            values: self.values,
+        }
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    // The values to bind, in the order of the parameters in the sql
+    pub fn values(&self) -> &[FilterValue] {
+        &self.values
+    }
+}
+
+impl FilterTerm {
+    fn compare(mut self, operator: &str, value: impl Into<FilterValue>) -> Filter {
+        self.values.push(value.into());
+        Filter {
+            sql: format!("{} {} ?", self.sql, operator),
+            values: self.values,
+        }
+    }
+
+    pub fn eq(self, value: impl Into<FilterValue>) -> Filter {
+        self.compare("=", value)
+    }
+
+    pub fn ne(self, value: impl Into<FilterValue>) -> Filter {
+        self.compare("!=", value)
+    }
+
+    pub fn lt(self, value: impl Into<FilterValue>) -> Filter {
+        self.compare("<", value)
+    }
+
+    pub fn le(self, value: impl Into<FilterValue>) -> Filter {
+        self.compare("<=", value)
+    }
+
+    pub fn gt(self, value: impl Into<FilterValue>) -> Filter {
+        self.compare(">", value)
+    }
+
+    pub fn ge(self, value: impl Into<FilterValue>) -> Filter {
+        self.compare(">=", value)
+    }
+
+    // Matches NULL columns, eg. an Option field that is None
+    pub fn is_null(self) -> Filter {
+        Filter {
+            sql: format!("{} IS NULL", self.sql),
+            values: self.values,
+        }
+    }
+
+    pub fn is_not_null(self) -> Filter {
+        Filter {
+            sql: format!("{} IS NOT NULL", self.sql),
+            values: self.values,
+        }
+    }
+}
@@ -1,9 +1,25 @@
+pub mod audit;
+pub mod clock;
+pub mod codegen;
 pub mod database_entity;
 pub mod database_query;
 pub mod database_resource;
+pub mod embedded;
+pub mod filter;
+pub mod migrations;
+pub mod schema;
 
+pub use audit::*;
+pub use clock::*;
 pub use database_entity::*;
 pub use database_resource::*;
+pub use embedded::*;
+pub use filter::*;
+pub use migrations::*;
+pub use schema::*;
+
+#[cfg(feature = "uuid")]
+pub use uuid;
 
 use database_query::{DatabaseEntityWithRequest, DatabaseQuery as DatabaseQueryInternal};
 pub type DatabaseQuery<'w, 's, DBQueryInfo> =
@@ -11,6 +27,9 @@ pub type DatabaseQuery<'w, 's, DBQueryInfo> =
 
 pub use database_query::ComponentMapper;
 
+// The most parameters SQLite binds in one statement, multi-row inserts are split to stay within it
+pub const SQLITE_MAX_BIND_PARAMETERS: usize = 32766;
+
 use generational_arena::Index as GenIndex;
 use sqlx::FromRow;
 use sqlx::Row;
@@ -20,24 +39,43 @@ pub struct DataseBaseEntityAndComponent<C> {
     pub component: C,
 }
 
-impl FromRow<'_, sqlx::sqlite::SqliteRow> for DatabaseEntity {
-    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+impl DatabaseEntity {
+    // Reads the database entity from a row where the key is stored in the `key_columns` columns
+    // More than one key column gives a composite key
+    pub fn from_row_with_key(
+        row: &sqlx::sqlite::SqliteRow,
+        key_columns: &[&str],
+    ) -> Result<Self, sqlx::Error> {
+        let id = match key_columns {
+            [key_column] => row.try_get(*key_column)?,
+            _ => DatabaseEntityId::Composite(
+                key_columns
+                    .iter()
+                    .map(|key_column| row.try_get(*key_column))
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
         Ok(DatabaseEntity {
-            id: row.try_get("id")?,
+            id,
             persisted: Persisted(true),
             dirty: false,
+            deleted: false,
             //Change when instantiated
             request: RequestId(GenIndex::from_raw_parts(0, 0)),
         })
     }
 }
 
-impl<'a, C: FromRow<'a, sqlx::sqlite::SqliteRow>> FromRow<'a, sqlx::sqlite::SqliteRow>
-    for DataseBaseEntityAndComponent<C>
+// The entity is read from the key columns of the component's table
+impl<'a, C: ComponentMapper + FromRow<'a, sqlx::sqlite::SqliteRow>>
+    FromRow<'a, sqlx::sqlite::SqliteRow> for DataseBaseEntityAndComponent<C>
 {
     fn from_row(row: &'a sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let key_columns = C::table_schema().primary_key;
+        let key_columns = key_columns.iter().map(String::as_str).collect::<Vec<_>>();
         Ok(DataseBaseEntityAndComponent {
-            entity: DatabaseEntity::from_row(row)?,
+            entity: DatabaseEntity::from_row_with_key(row, &key_columns)?,
             component: C::from_row(row)?,
         })
     }
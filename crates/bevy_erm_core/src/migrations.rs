@@ -0,0 +1,252 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::prelude::*;
+use futures::executor::block_on;
+
+use crate::database_resource::SqlxSqliteDatabaseResource;
+
+// The table recording which migrations have been applied to the database
+pub const MIGRATIONS_TABLE: &str = "_bevy_erm_migrations";
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub description: String,
+    pub sql: String,
+}
+
+impl Migration {
+    pub fn new(version: i64, description: &str, sql: &str) -> Self {
+        Migration {
+            version,
+            description: description.to_string(),
+            sql: sql.to_string(),
+        }
+    }
+
+    // Migration files are named `<version>_<description>.sql`, eg. `0001_create_users.sql`
+    pub fn from_file(file_name: &str, sql: &str) -> Result<Self, MigrationError> {
+        let invalid = || MigrationError::InvalidFileName(file_name.to_string());
+
+        let stem = file_name.strip_suffix(".sql").ok_or_else(invalid)?;
+        let (version, description) = stem.split_once('_').unwrap_or((stem, ""));
+        let version = version.parse::<i64>().map_err(|_| invalid())?;
+
+        Ok(Migration::new(version, &description.replace('_', " "), sql))
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Io(std::io::Error),
+    Sqlx(sqlx::Error),
+    InvalidFileName(String),
+    DuplicateVersion(i64),
+    // The database has a migration applied that the code does not know about
+    UnknownVersion(i64),
+    // The database has been migrated by a newer version of the code
+    DatabaseAhead {
+        database_version: i64,
+        code_version: i64,
+    },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Io(error) => write!(f, "failed to read migrations: {}", error),
+            MigrationError::Sqlx(error) => write!(f, "failed to apply migrations: {}", error),
+            MigrationError::InvalidFileName(file_name) => write!(
+                f,
+                "migration file {} is not named <version>_<description>.sql",
+                file_name
+            ),
+            MigrationError::DuplicateVersion(version) => {
+                write!(f, "more than one migration has version {}", version)
+            }
+            MigrationError::UnknownVersion(version) => {
+                write!(f, "the database has unknown migration {} applied", version)
+            }
+            MigrationError::DatabaseAhead {
+                database_version,
+                code_version,
+            } => write!(
+                f,
+                "the database is at version {} but the latest migration is {}",
+                database_version, code_version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<std::io::Error> for MigrationError {
+    fn from(error: std::io::Error) -> Self {
+        MigrationError::Io(error)
+    }
+}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(error: sqlx::Error) -> Self {
+        MigrationError::Sqlx(error)
+    }
+}
+
+// The migrations of the schema, applied in order of their version
+// Inserted as a resource the EntityRelationMapperPlugin applies them at startup
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    pub fn new(mut migrations: Vec<Migration>) -> Result<Self, MigrationError> {
+        migrations.sort_by_key(|migration| migration.version);
+        if let Some(duplicate) = migrations
+            .windows(2)
+            .find(|pair| pair[0].version == pair[1].version)
+        {
+            return Err(MigrationError::DuplicateVersion(duplicate[0].version));
+        }
+
+        Ok(Migrations { migrations })
+    }
+
+    // Builds the migrations from (file name, sql) pairs, used by embed_migrations!
+    pub fn from_files(files: Vec<(&str, &str)>) -> Result<Self, MigrationError> {
+        Migrations::new(
+            files
+                .into_iter()
+                .map(|(file_name, sql)| Migration::from_file(file_name, sql))
+                .collect::<Result<_, _>>()?,
+        )
+    }
+
+    // Reads every `.sql` file in the directory
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, MigrationError> {
+        let mut migrations = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "sql")
+            {
+                continue;
+            }
+
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            let sql = std::fs::read_to_string(&path)?;
+            migrations.push(Migration::from_file(&file_name, &sql)?);
+        }
+
+        Migrations::new(migrations)
+    }
+
+    // Writes the sql as the migration after the latest one in the directory
+    // and returns the path of the new file
+    pub fn write_migration(
+        dir: impl AsRef<Path>,
+        description: &str,
+        sql: &str,
+    ) -> Result<PathBuf, MigrationError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let version = Migrations::from_dir(dir)?.latest_version() + 1;
+        let description = description
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join("_")
+            .to_lowercase();
+        let path = dir.join(format!("{:04}_{}.sql", version, description));
+        std::fs::write(&path, sql)?;
+
+        Ok(path)
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    // The version of the latest migration, 0 if there are none
+    pub fn latest_version(&self) -> i64 {
+        self.migrations
+            .last()
+            .map_or(0, |migration| migration.version)
+    }
+
+    // Applies the migrations not yet applied to the database and returns their versions
+    // Each migration is applied in its own transaction along with its bookkeeping row
+    pub fn run(&self, db: &SqlxSqliteDatabaseResource) -> Result<Vec<i64>, MigrationError> {
+        let pool = db.pool();
+
+        block_on(async {
+            sqlx::query(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (version INTEGER PRIMARY KEY, description TEXT NOT NULL)",
+                MIGRATIONS_TABLE
+            ))
+            .execute(&pool)
+            .await?;
+
+            let applied = sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT version FROM {} ORDER BY version",
+                MIGRATIONS_TABLE
+            ))
+            .fetch_all(&pool)
+            .await?;
+
+            if let Some(&database_version) = applied.last() {
+                if database_version > self.latest_version() {
+                    return Err(MigrationError::DatabaseAhead {
+                        database_version,
+                        code_version: self.latest_version(),
+                    });
+                }
+            }
+            if let Some(&unknown) = applied.iter().find(|version| {
+                !self
+                    .migrations
+                    .iter()
+                    .any(|migration| migration.version == **version)
+            }) {
+                return Err(MigrationError::UnknownVersion(unknown));
+            }
+
+            let mut newly_applied = Vec::new();
+            for migration in &self.migrations {
+                if applied.contains(&migration.version) {
+                    continue;
+                }
+
+                let mut tr = pool.begin().await?;
+                let result = async {
+                    sqlx::query(&migration.sql).execute(&mut *tr).await?;
+                    sqlx::query(&format!(
+                        "INSERT INTO {} (version, description) VALUES (?, ?)",
+                        MIGRATIONS_TABLE
+                    ))
+                    .bind(migration.version)
+                    .bind(&migration.description)
+                    .execute(&mut *tr)
+                    .await
+                }
+                .await;
+
+                // Roll back straight away rather than when the connection is next used
+                match result {
+                    Ok(_) => tr.commit().await?,
+                    Err(error) => {
+                        tr.rollback().await?;
+                        return Err(error.into());
+                    }
+                }
+
+                newly_applied.push(migration.version);
+            }
+
+            Ok(newly_applied)
+        })
+    }
+}
@@ -0,0 +1,556 @@
+use std::fmt::Display;
+
+use bevy_ecs::prelude::*;
+use bevy_utils::all_tuples;
+use futures::executor::block_on;
+
+use crate::database_entity::*;
+use crate::database_query::ComponentMapper;
+use crate::database_resource::{DatabaseResource, SqlxSqliteDatabaseResource};
+
+// The SQL type a rust type is stored as in a column
+pub trait SqlColumnType {
+    const SQL_TYPE: &'static str;
+    const NULLABLE: bool = false;
+}
+
+macro_rules! sql_column_type {
+    ($sql_type:literal, $($ty:ty),*) => {
+        $(
+            impl SqlColumnType for $ty {
+                const SQL_TYPE: &'static str = $sql_type;
+            }
+        )*
+    };
+}
+
+sql_column_type!("INTEGER", i8, i16, i32, i64, u8, u16, u32, DatabaseEntityId);
+sql_column_type!("REAL", f32, f64);
+sql_column_type!("BOOLEAN", bool);
+sql_column_type!("TEXT", String, &str);
+sql_column_type!("BLOB", Vec<u8>, &[u8]);
+
+#[cfg(feature = "uuid")]
+sql_column_type!("BLOB", uuid::Uuid);
+
+impl<T: SqlColumnType> SqlColumnType for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+    const NULLABLE: bool = true;
+}
+
+impl DatabaseKeyType {
+    // The SQL type of a key column holding keys of this type
+    pub fn sql_type(&self) -> &'static str {
+        match self {
+            DatabaseKeyType::Integer => "INTEGER",
+            DatabaseKeyType::Text => "TEXT",
+            #[cfg(feature = "uuid")]
+            DatabaseKeyType::Uuid => "BLOB",
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub sql_type: String,
+    pub nullable: bool,
+    pub default: Option<String>,
+}
+
+impl ColumnSchema {
+    pub fn new(name: &str, sql_type: &str, nullable: bool) -> Self {
+        ColumnSchema {
+            name: name.to_string(),
+            sql_type: sql_type.to_string(),
+            nullable,
+            default: None,
+        }
+    }
+
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    // SQLite only adds NOT NULL columns that have a default
+    pub fn add_column_sql(&self, table: &str) -> String {
+        let column = if self.nullable || self.default.is_some() {
+            self.definition()
+        } else {
+            ColumnSchema {
+                nullable: true,
+                ..self.clone()
+            }
+            .definition()
+        };
+
+        format!("ALTER TABLE {} ADD COLUMN {}", table, column)
+    }
+
+    fn definition(&self) -> String {
+        let mut definition = format!("{} {}", self.name, self.sql_type);
+        if !self.nullable {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(default) = &self.default {
+            definition.push_str(&format!(" DEFAULT {}", default));
+        }
+        definition
+    }
+}
+
+// A column holding the key of a row of another table, from #[references = "table"]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ForeignKey {
+    pub column: String,
+    pub table: String,
+}
+
+impl ForeignKey {
+    pub fn new(column: &str, table: &str) -> Self {
+        ForeignKey {
+            column: column.to_string(),
+            table: table.to_string(),
+        }
+    }
+}
+
+// The table a component is stored in, as described by DBQueryDerive
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+    pub primary_key: Vec<String>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+impl TableSchema {
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    // Adds the columns of another component stored in the same table
+    // Columns already in the table are kept as they are
+    pub fn merge(&mut self, other: &TableSchema) {
+        for column in &other.columns {
+            if self.column(&column.name).is_none() {
+                self.columns.push(column.clone());
+            }
+        }
+        for foreign_key in &other.foreign_keys {
+            if !self.foreign_keys.contains(foreign_key) {
+                self.foreign_keys.push(foreign_key.clone());
+            }
+        }
+    }
+
+    pub fn create_table_sql(&self) -> String {
+        let mut definitions = self
+            .columns
+            .iter()
+            .map(|column| column.definition())
+            .collect::<Vec<String>>();
+        definitions.push(format!("PRIMARY KEY ({})", self.primary_key.join(", ")));
+        // The rows of a table are written in no particular order, so a reference from a table to
+        // itself is only checked when the request commits
+        definitions.extend(self.foreign_keys.iter().map(|foreign_key| {
+            let deferred = if foreign_key.table == self.name {
+                " DEFERRABLE INITIALLY DEFERRED"
+            } else {
+                ""
+            };
+            format!(
+                "FOREIGN KEY ({}) REFERENCES {}{}",
+                foreign_key.column, foreign_key.table, deferred
+            )
+        }));
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            self.name,
+            definitions.join(", ")
+        )
+    }
+}
+
+// Merges the schemas of components mapped to the same table into one schema per table
+// The tables are kept in the order they are first seen
+pub fn merge_table_schemas(schemas: Vec<TableSchema>) -> Vec<TableSchema> {
+    let mut tables: Vec<TableSchema> = Vec::new();
+    for schema in schemas {
+        match tables.iter_mut().find(|table| table.name == schema.name) {
+            Some(table) => table.merge(&schema),
+            None => tables.push(schema),
+        }
+    }
+    tables
+}
+
+// The foreign keys between the tables form a cycle, so there is no order to insert their rows in
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ForeignKeyCycle {
+    // The tables in the cycle, starting and ending with the same table
+    pub tables: Vec<String>,
+}
+
+impl Display for ForeignKeyCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the foreign keys of the tables {} form a cycle so their rows can not be ordered",
+            self.tables.join(" -> ")
+        )
+    }
+}
+
+impl std::error::Error for ForeignKeyCycle {}
+
+// Orders the tables so each comes after the tables it references, the order rows are inserted in
+// Rows are deleted in the reverse order. References to tables that are not given, and from a table
+// to itself, do not constrain the order, the latter being deferred to the commit by create_table_sql.
+// Otherwise the tables keep the order they are given in
+pub fn tables_in_dependency_order(tables: &[TableSchema]) -> Result<Vec<String>, ForeignKeyCycle> {
+    fn visit(
+        table: &TableSchema,
+        tables: &[TableSchema],
+        path: &mut Vec<String>,
+        ordered: &mut Vec<String>,
+    ) -> Result<(), ForeignKeyCycle> {
+        if ordered.contains(&table.name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|name| name == &table.name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(table.name.clone());
+            return Err(ForeignKeyCycle { tables: cycle });
+        }
+
+        path.push(table.name.clone());
+        for foreign_key in &table.foreign_keys {
+            if foreign_key.table == table.name {
+                continue;
+            }
+            if let Some(parent) = tables
+                .iter()
+                .find(|parent| parent.name == foreign_key.table)
+            {
+                visit(parent, tables, path, ordered)?;
+            }
+        }
+        path.pop();
+
+        ordered.push(table.name.clone());
+        Ok(())
+    }
+
+    let tables = merge_table_schemas(tables.to_vec());
+    let mut ordered = Vec::new();
+    for table in &tables {
+        visit(table, &tables, &mut Vec::new(), &mut ordered)?;
+    }
+
+    Ok(ordered)
+}
+
+// The table schemas of a component mapper or a tuple of component mappers
+pub trait TableSchemas {
+    fn table_schemas() -> Vec<TableSchema>;
+}
+
+impl<T: ComponentMapper> TableSchemas for T {
+    fn table_schemas() -> Vec<TableSchema> {
+        vec![T::table_schema()]
+    }
+}
+
+macro_rules! table_schemas_of_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: TableSchemas),*> TableSchemas for ($($name,)*) {
+            fn table_schemas() -> Vec<TableSchema> {
+                let mut schemas = Vec::new();
+                $(schemas.extend($name::table_schemas());)*
+                schemas
+            }
+        }
+    };
+}
+
+all_tuples!(table_schemas_of_tuple, 1, 12, T);
+
+// The type affinity SQLite gives a column from its declared type
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    // See https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+    fn of(declared_type: &str) -> Affinity {
+        let declared_type = declared_type.to_uppercase();
+        if declared_type.contains("INT") {
+            Affinity::Integer
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|text| declared_type.contains(text))
+        {
+            Affinity::Text
+        } else if declared_type.is_empty() || declared_type.contains("BLOB") {
+            Affinity::Blob
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|real| declared_type.contains(real))
+        {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    // Integers and booleans are both stored in integer or numeric columns
+    fn is_compatible(self, found: Affinity) -> bool {
+        match (self, found) {
+            (Affinity::Integer | Affinity::Numeric, Affinity::Integer | Affinity::Numeric) => true,
+            (Affinity::Real, Affinity::Real | Affinity::Numeric) => true,
+            (expected, found) => expected == found,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SchemaMismatch {
+    MissingTable {
+        table: String,
+    },
+    MissingColumn {
+        table: String,
+        column: String,
+    },
+    IncompatibleType {
+        table: String,
+        column: String,
+        expected: String,
+        found: String,
+    },
+}
+
+impl Display for SchemaMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaMismatch::MissingTable { table } => write!(f, "table {} does not exist", table),
+            SchemaMismatch::MissingColumn { table, column } => {
+                write!(f, "column {}.{} does not exist", table, column)
+            }
+            SchemaMismatch::IncompatibleType {
+                table,
+                column,
+                expected,
+                found,
+            } => write!(
+                f,
+                "column {}.{} is {} but the component stores {}",
+                table, column, found, expected
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SchemaValidationError {
+    Sqlx(sqlx::Error),
+    // Every mismatch between the components and the database
+    Mismatches(Vec<SchemaMismatch>),
+}
+
+impl Display for SchemaValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaValidationError::Sqlx(error) => {
+                write!(f, "failed to read the database schema: {}", error)
+            }
+            SchemaValidationError::Mismatches(mismatches) => {
+                write!(f, "the database schema does not match the components:")?;
+                for mismatch in mismatches {
+                    write!(f, "\n  {}", mismatch)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaValidationError {}
+
+impl From<sqlx::Error> for SchemaValidationError {
+    fn from(error: sqlx::Error) -> Self {
+        SchemaValidationError::Sqlx(error)
+    }
+}
+
+// Reads the columns of a table from the database as (name, declared type) pairs
+// A table that does not exist has no columns
+pub async fn table_columns(
+    pool: &sqlx::SqlitePool,
+    table: &str,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, String)>("SELECT name, type FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await
+}
+
+// The tables of the components checked against the database at startup
+// The EntityRelationMapperPlugin validates them before any other startup systems run
+#[derive(Resource, Clone, Debug, Default)]
+pub struct SchemaValidation {
+    tables: Vec<TableSchema>,
+}
+
+impl SchemaValidation {
+    pub fn register<T: TableSchemas>(&mut self) -> &mut Self {
+        self.tables.extend(T::table_schemas());
+        self
+    }
+
+    pub fn tables(&self) -> Vec<TableSchema> {
+        merge_table_schemas(self.tables.clone())
+    }
+
+    // Creates the tables of the registered components that do not exist yet, in one transaction
+    pub fn create_tables(&self, db: &SqlxSqliteDatabaseResource) -> Result<(), sqlx::Error> {
+        let request = db.start_new_transaction();
+        block_on(async {
+            let tr = db.get_transaction(request);
+            let mut guard = tr.lock().await;
+            let tr = guard.a.as_mut().unwrap();
+
+            for table in self.tables() {
+                sqlx::query(&table.create_table_sql())
+                    .execute(&mut **tr)
+                    .await?;
+            }
+            Ok::<(), sqlx::Error>(())
+        })?;
+        db.commit_transaction(request);
+
+        Ok(())
+    }
+
+    // Every column of the registered components that is missing from the database
+    // or is stored with an incompatible type
+    pub fn mismatches(
+        &self,
+        db: &SqlxSqliteDatabaseResource,
+    ) -> Result<Vec<SchemaMismatch>, sqlx::Error> {
+        let pool = db.pool();
+        let mut mismatches = Vec::new();
+
+        for table in self.tables() {
+            let columns = block_on(table_columns(&pool, &table.name))?;
+            if columns.is_empty() {
+                mismatches.push(SchemaMismatch::MissingTable {
+                    table: table.name.clone(),
+                });
+                continue;
+            }
+
+            for column in &table.columns {
+                let found = columns
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(&column.name));
+                match found {
+                    None => mismatches.push(SchemaMismatch::MissingColumn {
+                        table: table.name.clone(),
+                        column: column.name.clone(),
+                    }),
+                    Some((_, found_type)) => {
+                        // Columns declared without a type accept any value
+                        if !found_type.is_empty()
+                            && !Affinity::of(&column.sql_type)
+                                .is_compatible(Affinity::of(found_type))
+                        {
+                            mismatches.push(SchemaMismatch::IncompatibleType {
+                                table: table.name.clone(),
+                                column: column.name.clone(),
+                                expected: column.sql_type.clone(),
+                                found: found_type.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    // Checks every column of the registered components exists with a compatible type
+    // and reports all of the mismatches together
+    pub fn validate(&self, db: &SqlxSqliteDatabaseResource) -> Result<(), SchemaValidationError> {
+        let mismatches = self.mismatches(db)?;
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemaValidationError::Mismatches(mismatches))
+        }
+    }
+
+    // The statements that bring the database in line with the registered components
+    pub fn diff(&self, db: &SqlxSqliteDatabaseResource) -> Result<SchemaDiff, sqlx::Error> {
+        let tables = self.tables();
+        let mut diff = SchemaDiff::default();
+
+        for mismatch in self.mismatches(db)? {
+            match &mismatch {
+                SchemaMismatch::MissingTable { table } => {
+                    let table = tables.iter().find(|t| &t.name == table).unwrap();
+                    diff.statements.push(table.create_table_sql());
+                }
+                SchemaMismatch::MissingColumn { table, column } => {
+                    let column = tables
+                        .iter()
+                        .find(|t| &t.name == table)
+                        .and_then(|t| t.column(column))
+                        .unwrap();
+                    diff.statements.push(column.add_column_sql(table));
+                }
+                // SQLite can not change the type of a column so these are left to be resolved by hand
+                SchemaMismatch::IncompatibleType { .. } => diff.unresolved.push(mismatch),
+            }
+        }
+
+        Ok(diff)
+    }
+}
+
+// The changes needed to bring the database in line with the components
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct SchemaDiff {
+    pub statements: Vec<String>,
+    // Mismatches that can not be fixed with a statement
+    pub unresolved: Vec<SchemaMismatch>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty() && self.unresolved.is_empty()
+    }
+
+    // The statements as a migration, with the unresolved mismatches as comments
+    pub fn to_sql(&self) -> String {
+        let mut sql = String::new();
+        for mismatch in &self.unresolved {
+            sql.push_str(&format!("-- TODO: {}\n", mismatch));
+        }
+        for statement in &self.statements {
+            sql.push_str(statement);
+            sql.push_str(";\n");
+        }
+        sql
+    }
+}
@@ -0,0 +1,86 @@
+use quote::quote;
+use syn::{Data, DeriveInput, Fields};
+
+use crate::fields::get_column_fields;
+
+pub fn embedded_derive(ast: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &ast.ident;
+
+    let Data::Struct(data) = &ast.data else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "DBEmbedded only supports structs with named fields",
+        ));
+    };
+    if !matches!(data.fields, Fields::Named(_)) {
+        return Err(syn::Error::new_spanned(
+            &data.fields,
+            "DBEmbedded only supports structs with named fields",
+        ));
+    }
+
+    let column_fields = get_column_fields(data)?;
+    if let Some(field) = column_fields
+        .iter()
+        .find(|field| field.main_key || field.readonly || field.flatten.is_some())
+    {
+        return Err(syn::Error::new_spanned(
+            &field.ident,
+            "the fields of an embedded value can not be #[main_key], #[readonly] or #[flatten]",
+        ));
+    }
+
+    let loaded = column_fields
+        .iter()
+        .filter(|field| field.is_loaded())
+        .collect::<Vec<_>>();
+    let loaded_idents = loaded.iter().map(|field| &field.ident);
+    let columns = loaded.iter().map(|field| &field.column).collect::<Vec<_>>();
+    let values = loaded.iter().map(|field| {
+        let column = &field.column;
+        field.get_value(&quote!(format!("{}{}", prefix, #column).as_str()))
+    });
+    let schemas = loaded.iter().map(|field| {
+        let column = &field.column;
+        let (sql_type, nullable) = field.sql_type();
+        quote! {
+            bevy_erm_core::ColumnSchema::new(&format!("{}{}", prefix, #column), #sql_type, #nullable)
+        }
+    });
+    let binds = loaded.iter().map(|field| field.bind_call(&quote!(self)));
+    let skipped_idents = column_fields
+        .iter()
+        .filter(|field| !field.is_loaded())
+        .map(|field| &field.ident);
+
+    Ok(quote! {
+        impl bevy_erm_core::Embedded for #ident {
+            fn columns(prefix: &str) -> Vec<String> {
+                vec![#(format!("{}{}", prefix, #columns)),*]
+            }
+
+            fn column_schemas(prefix: &str) -> Vec<bevy_erm_core::ColumnSchema> {
+                vec![#(#schemas),*]
+            }
+
+            fn from_row(
+                row: &sqlx::sqlite::SqliteRow,
+                prefix: &str,
+            ) -> Result<Self, sqlx::Error> {
+                use sqlx::Row;
+
+                Ok(#ident {
+                    #(#loaded_idents: #values,)*
+                    #(#skipped_idents: Default::default(),)*
+                })
+            }
+
+            fn bind<'q>(
+                &self,
+                query: bevy_erm_core::SqliteQuery<'q>,
+            ) -> bevy_erm_core::SqliteQuery<'q> {
+                query #(#binds)*
+            }
+        }
+    })
+}
@@ -0,0 +1,164 @@
+use quote::quote;
+use syn::{DataEnum, DeriveInput, Fields, Ident};
+
+use crate::{find_attribute, get_string_attribute, string_attribute_value};
+
+// How the variants of a fieldless enum are stored in its column
+enum EnumType {
+    // The variant name, or its #[rename = "..."] value
+    Text(Vec<String>),
+    // The discriminant of the variant
+    Integer,
+}
+
+pub struct EnumVariants<'a> {
+    ident: &'a Ident,
+    variants: Vec<&'a Ident>,
+    enum_type: EnumType,
+}
+
+impl EnumVariants<'_> {
+    pub fn sql_type(&self) -> &'static str {
+        match self.enum_type {
+            EnumType::Text(_) => "TEXT",
+            EnumType::Integer => "INTEGER",
+        }
+    }
+
+    // Implements the sqlx encoding of the enum and its SqlColumnType
+    // so it can be used as the type of a field
+    pub fn encoding_impl(&self) -> proc_macro2::TokenStream {
+        let ident = self.ident;
+        let variants = &self.variants;
+        let sql_type = self.sql_type();
+
+        let (repr, encoded, decode) = match &self.enum_type {
+            EnumType::Text(values) => (
+                quote!(&str),
+                quote! {
+                    match self {
+                        #(#ident::#variants => #values,)*
+                    }
+                },
+                quote! {
+                    match value {
+                        #(#values => Ok(#ident::#variants),)*
+                        _ => Err(format!("unknown {} value {}", stringify!(#ident), value).into()),
+                    }
+                },
+            ),
+            EnumType::Integer => (
+                quote!(i64),
+                quote! {
+                    match self {
+                        #(#ident::#variants => #ident::#variants as i64,)*
+                    }
+                },
+                quote! {
+                    #(
+                        if value == #ident::#variants as i64 {
+                            return Ok(#ident::#variants);
+                        }
+                    )*
+                    Err(format!("unknown {} value {}", stringify!(#ident), value).into())
+                },
+            ),
+        };
+
+        quote! {
+            impl sqlx::Type<sqlx::Sqlite> for #ident {
+                fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+                    <#repr as sqlx::Type<sqlx::Sqlite>>::type_info()
+                }
+
+                fn compatible(ty: &sqlx::sqlite::SqliteTypeInfo) -> bool {
+                    <#repr as sqlx::Type<sqlx::Sqlite>>::compatible(ty)
+                }
+            }
+
+            impl<'q> sqlx::Encode<'q, sqlx::Sqlite> for #ident {
+                fn encode_by_ref(
+                    &self,
+                    buf: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>,
+                ) -> sqlx::encode::IsNull {
+                    let value: #repr = #encoded;
+                    <#repr as sqlx::Encode<'q, sqlx::Sqlite>>::encode(value, buf)
+                }
+            }
+
+            impl<'r> sqlx::Decode<'r, sqlx::Sqlite> for #ident {
+                fn decode(
+                    value: sqlx::sqlite::SqliteValueRef<'r>,
+                ) -> Result<Self, sqlx::error::BoxDynError> {
+                    let value = <#repr as sqlx::Decode<'r, sqlx::Sqlite>>::decode(value)?;
+                    #decode
+                }
+            }
+
+            impl bevy_erm_core::SqlColumnType for #ident {
+                const SQL_TYPE: &'static str = #sql_type;
+            }
+        }
+    }
+}
+
+// Reads the variants of a fieldless enum, stored as text unless it has #[enum_type = "integer"]
+pub fn get_enum_variants<'a>(
+    ast: &'a DeriveInput,
+    data: &'a DataEnum,
+) -> syn::Result<EnumVariants<'a>> {
+    let integer = match get_string_attribute(&ast.attrs, "enum_type")?.as_deref() {
+        None | Some("text") => false,
+        Some("integer") => true,
+        Some(enum_type) => {
+            return Err(syn::Error::new_spanned(
+                find_attribute(&ast.attrs, "enum_type"),
+                format!(
+                    "unknown enum_type \"{}\", expected \"text\" or \"integer\"",
+                    enum_type
+                ),
+            ))
+        }
+    };
+
+    let mut variants = Vec::new();
+    let mut values = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                &variant.fields,
+                "only enums without fields can be stored in a column",
+            ));
+        }
+
+        let rename = find_attribute(&variant.attrs, "rename");
+        if let (true, Some(rename)) = (integer, rename) {
+            return Err(syn::Error::new_spanned(
+                rename,
+                "#[rename] only applies to enums stored as text",
+            ));
+        }
+        values.push(match rename {
+            Some(rename) => string_attribute_value(rename, "rename")?,
+            None => variant.ident.to_string(),
+        });
+        variants.push(&variant.ident);
+    }
+
+    if variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &ast.ident,
+            "an enum stored in a column needs at least one variant",
+        ));
+    }
+
+    Ok(EnumVariants {
+        ident: &ast.ident,
+        variants,
+        enum_type: if integer {
+            EnumType::Integer
+        } else {
+            EnumType::Text(values)
+        },
+    })
+}
@@ -0,0 +1,236 @@
+use quote::quote;
+use syn::{DataStruct, Ident};
+
+use crate::sql::ColumnTerm;
+
+// How a field of the component is mapped to a column of the table
+pub struct ColumnField {
+    pub ident: Ident,
+    pub ty: syn::Type,
+    // The column in the table, from #[column = "..."] otherwise the field name
+    pub column: String,
+    // Marked with #[main_key]
+    pub main_key: bool,
+    // Not stored in the database, from #[skip]
+    // The field is filled with its Default when loaded
+    pub skip: bool,
+    // Loaded but never written, eg. columns generated by the database
+    pub readonly: bool,
+    // Serialised with serde into a TEXT column, from #[json]
+    pub json: bool,
+    // The prefix of the columns of an embedded value object, from #[flatten(prefix = "...")]
+    pub flatten: Option<String>,
+    // The table whose key the column holds, from #[references = "..."]
+    pub references: Option<String>,
+    // Set from the Clock resource when the component is inserted, from #[created_at]
+    pub created_at: bool,
+    // Set from the Clock resource when the component is inserted or updated, from #[updated_at]
+    pub updated_at: bool,
+}
+
+impl ColumnField {
+    // Whether the field is read from the table
+    pub fn is_loaded(&self) -> bool {
+        !self.skip
+    }
+
+    // Whether the field is written to the table on insert and update
+    pub fn is_written(&self) -> bool {
+        !self.skip && !self.readonly
+    }
+
+    pub fn column_term(&self) -> ColumnTerm {
+        match &self.flatten {
+            Some(prefix) => ColumnTerm::Flatten {
+                ty: Box::new(self.ty.clone()),
+                prefix: prefix.clone(),
+            },
+            None => ColumnTerm::Column(self.column.clone()),
+        }
+    }
+
+    // Binds the field of `receiver` to the query, an embedded value binds each of its fields
+    pub fn bind_call(&self, receiver: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let ident = &self.ident;
+        if self.flatten.is_some() {
+            quote!(.bind_embedded(&#receiver.#ident))
+        } else if self.json {
+            quote!(.bind(sqlx::types::Json(#receiver.#ident.clone())))
+        } else {
+            quote!(.bind(#receiver.#ident.clone()))
+        }
+    }
+
+    // Reads the field from `row`, where `column` evaluates to the name of its column
+    pub fn get_value(&self, column: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let ty = &self.ty;
+        if let Some(prefix) = &self.flatten {
+            quote!(<#ty as bevy_erm_core::Embedded>::from_row(row, #prefix)?)
+        } else if self.json {
+            quote!(row.try_get::<sqlx::types::Json<#ty>, _>(#column)?.0)
+        } else {
+            quote!(row.try_get(#column)?)
+        }
+    }
+
+    // The sql type of the column and whether it is nullable
+    pub fn sql_type(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let ty = &self.ty;
+        if self.json {
+            // An Option is stored as the JSON null rather than NULL
+            (quote!("TEXT"), quote!(false))
+        } else {
+            (
+                quote!(<#ty as bevy_erm_core::SqlColumnType>::SQL_TYPE),
+                quote!(<#ty as bevy_erm_core::SqlColumnType>::NULLABLE),
+            )
+        }
+    }
+}
+
+// The string of a #[name = "..."] attribute
+pub fn string_attribute_value(attr: &syn::Attribute, name: &str) -> syn::Result<String> {
+    let error = || syn::Error::new_spanned(attr, format!("expected #[{} = \"...\"]", name));
+
+    let syn::Meta::NameValue(name_value) = &attr.meta else {
+        return Err(error());
+    };
+    let syn::Expr::Lit(syn::ExprLit {
+        lit: syn::Lit::Str(value),
+        ..
+    }) = &name_value.value
+    else {
+        return Err(error());
+    };
+
+    Ok(value.value())
+}
+
+// Flags such as #[skip] take no value
+pub fn require_flag(attr: &syn::Attribute, name: &str) -> syn::Result<()> {
+    match attr.meta {
+        syn::Meta::Path(_) => Ok(()),
+        _ => Err(syn::Error::new_spanned(
+            attr,
+            format!("#[{}] does not take a value", name),
+        )),
+    }
+}
+
+pub fn get_column_fields(data: &DataStruct) -> syn::Result<Vec<ColumnField>> {
+    data.fields.iter().map(get_column_field).collect()
+}
+
+fn get_column_field(field: &syn::Field) -> syn::Result<ColumnField> {
+    let ident = field.ident.clone().unwrap();
+
+    let mut column = None;
+    let mut main_key = false;
+    let mut skip = false;
+    let mut readonly = false;
+    let mut json = false;
+    let mut flatten = None;
+    let mut references = None;
+    let mut created_at = false;
+    let mut updated_at = false;
+
+    for attr in &field.attrs {
+        let path = attr.path();
+        if path.is_ident("column") {
+            column = Some(string_attribute_value(attr, "column")?);
+        } else if path.is_ident("main_key") {
+            require_flag(attr, "main_key")?;
+            main_key = true;
+        } else if path.is_ident("skip") {
+            require_flag(attr, "skip")?;
+            skip = true;
+        } else if path.is_ident("readonly") {
+            require_flag(attr, "readonly")?;
+            readonly = true;
+        } else if path.is_ident("json") {
+            require_flag(attr, "json")?;
+            json = true;
+        } else if path.is_ident("flatten") {
+            flatten = Some(flatten_prefix(attr)?);
+        } else if path.is_ident("references") {
+            references = Some(string_attribute_value(attr, "references")?);
+        } else if path.is_ident("created_at") {
+            require_flag(attr, "created_at")?;
+            created_at = true;
+        } else if path.is_ident("updated_at") {
+            require_flag(attr, "updated_at")?;
+            updated_at = true;
+        }
+    }
+
+    let column = column.unwrap_or_else(|| ident.to_string());
+
+    if main_key && skip {
+        return Err(syn::Error::new_spanned(
+            field,
+            "a #[main_key] field is part of the key so can not be skipped",
+        ));
+    }
+
+    if main_key && json {
+        return Err(syn::Error::new_spanned(
+            field,
+            "a #[main_key] field can not be stored as JSON",
+        ));
+    }
+    if flatten.is_some() && (main_key || json) {
+        return Err(syn::Error::new_spanned(
+            field,
+            "a #[flatten] field can not also be a #[main_key] or #[json] field",
+        ));
+    }
+    if references.is_some() && (skip || json || flatten.is_some()) {
+        return Err(syn::Error::new_spanned(
+            field,
+            "a #[references] field holds a key so can not be #[skip], #[json] or #[flatten]",
+        ));
+    }
+
+    if (created_at || updated_at)
+        && (main_key || skip || readonly || json || flatten.is_some() || references.is_some())
+    {
+        return Err(syn::Error::new_spanned(
+            field,
+            "a #[created_at] or #[updated_at] field is written from the clock so can only be a plain column",
+        ));
+    }
+
+    Ok(ColumnField {
+        ident,
+        ty: field.ty.clone(),
+        column,
+        main_key,
+        skip,
+        readonly,
+        json,
+        flatten,
+        references,
+        created_at,
+        updated_at,
+    })
+}
+
+// #[flatten] or #[flatten(prefix = "billing_")]
+fn flatten_prefix(attr: &syn::Attribute) -> syn::Result<String> {
+    if let syn::Meta::Path(_) = attr.meta {
+        return Ok(String::new());
+    }
+
+    let mut prefix = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("prefix") {
+            let value: syn::LitStr = meta.value()?.parse()?;
+            prefix = Some(value.value());
+            Ok(())
+        } else {
+            Err(meta.error("expected #[flatten(prefix = \"...\")]"))
+        }
+    })?;
+
+    Ok(prefix.unwrap_or_default())
+}
@@ -1,44 +1,179 @@
-use casey::lower;
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 extern crate syn;
-use syn::{Data, DataStruct, DeriveInput, Ident};
+use syn::{parse_macro_input, Data, DataEnum, DataStruct, DeriveInput, Fields, Ident};
 extern crate proc_macro;
-extern crate casey;
 extern crate quote;
 
-#[proc_macro_derive(DBQueryDerive, attributes(main_key, table_name))]
+mod embedded;
+mod enums;
+mod fields;
+mod migrations;
+mod sql;
+use enums::*;
+use fields::*;
+use sql::*;
+
+// Embeds the migrations in a directory into the binary, see bevy_erm_core::Migrations
+// embed_migrations!("migrations") gives a Result<Migrations, MigrationError>
+#[proc_macro]
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    migrations::embed_migrations(input)
+}
+
+// Stores a fieldless enum in a column so it can be the type of a component field
+// Variants are stored by name, or by discriminant with #[enum_type = "integer"]
+#[proc_macro_derive(DBEnum, attributes(enum_type, rename))]
+pub fn enum_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    let Data::Enum(data) = &ast.data else {
+        return syn::Error::new_spanned(&ast.ident, "DBEnum only supports enums")
+            .into_compile_error()
+            .into();
+    };
+
+    get_enum_variants(&ast, data)
+        .map(|variants| variants.encoding_impl())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(
+    DBQueryDerive,
+    attributes(
+        main_key,
+        primary_key,
+        key_type,
+        table_name,
+        column,
+        skip,
+        readonly,
+        json,
+        flatten,
+        enum_type,
+        rename,
+        marker_table,
+        references,
+        upsert,
+        soft_delete,
+        created_at,
+        updated_at
+    )
+)]
 pub fn query_derive(input: TokenStream) -> TokenStream {
-    //TODO fix assumptions
-    // key parameter is called `id`
-    // id is always the first field
+    // The key column is `id` unless fields are marked with #[main_key]
+    // or the struct has a #[primary_key = "column"] attribute
 
     // Parse the input tokens into a syntax tree
-    let ast: DeriveInput = syn::parse(input).expect("Failed to parse input");
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    derive_component(&ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn derive_component(ast: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let is_marker = matches!(&ast.data, Data::Struct(data) if data.fields.is_empty());
+    if let Some(attr) = find_attribute(&ast.attrs, "soft_delete") {
+        if is_marker && find_attribute(&ast.attrs, "marker_table").is_some() {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "#[soft_delete] can not be used with #[marker_table], the row of the marker is deleted when it is removed",
+            ));
+        }
+    }
 
-    let Data::Struct(ref data) = ast.data else {
-        panic!("This derive macro only supports structs");
+    let data = match &ast.data {
+        Data::Struct(data) => data,
+        Data::Enum(data) => return enum_component(ast, data),
+        Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "DBQueryDerive only supports structs and enums",
+            ))
+        }
     };
 
+    if let Fields::Unnamed(fields) = &data.fields {
+        return Err(syn::Error::new_spanned(
+            fields,
+            "DBQueryDerive does not support tuple structs, name the fields after their columns",
+        ));
+    }
+
     if data.fields.is_empty() {
-        marker_component(&ast, data)
+        marker_component(ast, data)
     } else {
-        full_component(&ast, data)
+        full_component(ast, data)
     }
 }
 
+// Maps the fields of a value object to columns of the table of the components it is embedded in
+// with #[flatten(prefix = "...")]
+#[proc_macro_derive(DBEmbedded, attributes(column, skip, json))]
+pub fn embedded_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    embedded::embedded_derive(&ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// The queries loading every component in the table, <Ident>QueryLoadAll,
+// and the components matching a Filter, <Ident>QueryLoadWhere
+// `condition` is the part of the WHERE clause rows need to have the component,
+// with `condition_binds` binding its parameters
 fn get_load_all_query_impl(
     ast: &DeriveInput,
-    _data: &DataStruct,
-    load_all_query: String,
+    select_query: Sql,
+    condition: Option<String>,
+    condition_binds: proc_macro2::TokenStream,
+    main_key: &MainKey,
 ) -> proc_macro2::TokenStream {
     let ident = &ast.ident;
+    let key_columns = &main_key.columns;
 
     let load_all_struct = format_ident!("{}QueryLoadAll", ident);
+    let load_where_struct = format_ident!("{}QueryLoadWhere", ident);
+
+    let (load_all_query, load_where_query) = match condition {
+        Some(condition) => {
+            let condition = Sql::Static(condition);
+            (
+                Sql::format("{} WHERE {}", &[&select_query, &condition]),
+                Sql::format("{} WHERE {} AND ", &[&select_query, &condition]),
+            )
+        }
+        None => (
+            select_query.clone(),
+            Sql::format("{} WHERE ", &[&select_query]),
+        ),
+    };
 
     quote!(
         pub struct #load_all_struct(pub RequestId);
 
+        impl #load_all_struct {
+            // Fails on the first row that can not be decoded
+            fn components_from_rows(
+                rows: &[sqlx::sqlite::SqliteRow],
+                request: RequestId,
+            ) -> Result<Vec<(DatabaseEntity, #ident)>, ()> {
+                rows
+                    .iter()
+                    .map(|row| {
+                        let mut entity = DatabaseEntity::from_row_with_key(row, &[#(#key_columns),*])
+                            .map_err(|_| ())?;
+                        entity.request = request;
+                        let component = <#ident as sqlx::FromRow<sqlx::sqlite::SqliteRow>>::from_row(row)
+                            .map_err(|_| ())?;
+                        Ok((entity, component))
+                    })
+                    .collect()
+            }
+        }
+
         #[async_trait]
         impl CustomDatabaseQuery<SqlxSqliteDatabaseResource, #ident> for #load_all_struct {
             async fn query(
@@ -47,57 +182,227 @@ fn get_load_all_query_impl(
             ) -> Result<Vec<(DatabaseEntity, #ident)>, ()> {
                 let mut guard = tr.lock().await;
                 let tr = guard.a.as_mut().unwrap();
-                let db_entity_and_components = sqlx::query_as::<_, DataseBaseEntityAndComponent<#ident>>(#load_all_query)
+                let rows = sqlx::query(&#load_all_query)
+                    #condition_binds
                     .fetch_all(&mut **tr)
                     .await
-                    .unwrap();
-
-                let db_entity_and_components = db_entity_and_components
-                    .into_iter()
-                    .map(|db_entity_and_component| {
-                        let mut entity = db_entity_and_component.entity;
-                        entity.request = self.0;
-                        (
-                            entity,
-                            db_entity_and_component.component,
-                        )
-                    })
-                    .collect();
+                    .map_err(|_| ())?;
+
+                #load_all_struct::components_from_rows(&rows, self.0)
+            }
+        }
 
-                Ok(db_entity_and_components)
+        pub struct #load_where_struct(pub RequestId, pub Filter);
+
+        #[async_trait]
+        impl CustomDatabaseQuery<SqlxSqliteDatabaseResource, #ident> for #load_where_struct {
+            async fn query(
+                &self,
+                tr: DatabaseTransaction<SqlxSqliteDatabaseResource>,
+            ) -> Result<Vec<(DatabaseEntity, #ident)>, ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let sql = format!("{}({})", #load_where_query, self.1.sql());
+                let mut query = sqlx::query(&sql) #condition_binds;
+                for value in self.1.values() {
+                    query = query.bind(value.clone());
+                }
+                let rows = query.fetch_all(&mut **tr).await.map_err(|_| ())?;
+
+                #load_all_struct::components_from_rows(&rows, self.0)
             }
         }
     )
 }
 
-fn marker_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
+// The loaders of a #[soft_delete] component that also load deleted rows,
+// eg. UserQueryLoadAll(request).with_deleted()
+fn get_with_deleted_query_impl(ast: &DeriveInput, select_query: Sql) -> proc_macro2::TokenStream {
     let ident = &ast.ident;
+    let load_all_struct = format_ident!("{}QueryLoadAll", ident);
+    let load_where_struct = format_ident!("{}QueryLoadWhere", ident);
+    let load_where_query = Sql::format("{} WHERE ", &[&select_query]);
+
+    quote!(
+        impl #load_all_struct {
+            pub fn with_deleted(self) -> WithDeleted<Self> {
+                WithDeleted(self)
+            }
+        }
 
-    let marker_col = lower!(ident);
+        #[async_trait]
+        impl CustomDatabaseQuery<SqlxSqliteDatabaseResource, #ident> for WithDeleted<#load_all_struct> {
+            async fn query(
+                &self,
+                tr: DatabaseTransaction<SqlxSqliteDatabaseResource>,
+            ) -> Result<Vec<(DatabaseEntity, #ident)>, ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+                let rows = sqlx::query(&#select_query)
+                    .fetch_all(&mut **tr)
+                    .await
+                    .map_err(|_| ())?;
+
+                #load_all_struct::components_from_rows(&rows, self.0.0)
+            }
+        }
 
-    let table_name = get_table_name(ast);
-    let main_key_field = get_main_key(ast);
+        impl #load_where_struct {
+            pub fn with_deleted(self) -> WithDeleted<Self> {
+                WithDeleted(self)
+            }
+        }
 
-    let selection_query = format!(
-        "SELECT {} FROM {} WHERE {} = ?",
-        marker_col, table_name, main_key_field
+        #[async_trait]
+        impl CustomDatabaseQuery<SqlxSqliteDatabaseResource, #ident> for WithDeleted<#load_where_struct> {
+            async fn query(
+                &self,
+                tr: DatabaseTransaction<SqlxSqliteDatabaseResource>,
+            ) -> Result<Vec<(DatabaseEntity, #ident)>, ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let filter = &self.0.1;
+                let sql = format!("{}({})", #load_where_query, filter.sql());
+                let mut query = sqlx::query(&sql);
+                for value in filter.values() {
+                    query = query.bind(value.clone());
+                }
+                let rows = query.fetch_all(&mut **tr).await.map_err(|_| ())?;
+
+                #load_all_struct::components_from_rows(&rows, self.0.0)
+            }
+        }
+    )
+}
+
+// The column holding when the row was deleted, `deleted_at` unless given
+fn get_soft_delete_column(attr: &syn::Attribute) -> syn::Result<String> {
+    if let syn::Meta::Path(_) = attr.meta {
+        return Ok("deleted_at".to_string());
+    }
+
+    let mut column = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("column") {
+            let value: syn::LitStr = meta.value()?.parse()?;
+            column = Some(value.value());
+            Ok(())
+        } else {
+            Err(meta.error("expected #[soft_delete(column = \"...\")]"))
+        }
+    })?;
+
+    column.ok_or_else(|| syn::Error::new_spanned(attr, "expected #[soft_delete(column = \"...\")]"))
+}
+
+fn marker_component(ast: &DeriveInput, data: &DataStruct) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(attr) = find_attribute(&ast.attrs, "marker_table") {
+        return existence_marker_component(ast, data, attr);
+    }
+
+    let ident = &ast.ident;
+
+    // The marker column is the lowercase struct name unless given with #[column = "..."]
+    let marker_col = get_string_attribute(&ast.attrs, "column")?
+        .unwrap_or_else(|| ident.to_string().to_lowercase());
+
+    let table_name = get_table_name(ast)?;
+    let column_fields = get_column_fields(data)?;
+    let main_key = get_main_key(ast, &column_fields)?;
+    let key_binds = main_key.binds();
+    let key_impl = main_key.key_impl();
+
+    // Rows soft deleted by the other components of the table, given with the same #[soft_delete]
+    // attribute, do not have the marker
+    let soft_delete = find_attribute(&ast.attrs, "soft_delete")
+        .map(get_soft_delete_column)
+        .transpose()?;
+
+    // Markers are stored as a boolean column that is false for rows without the marker
+    let mut columns = main_key.column_schemas(&column_fields);
+    columns.push(quote! {
+        [ColumnSchema::new(#marker_col, "BOOLEAN", false).with_default("0")]
+    });
+    if let Some(column) = &soft_delete {
+        columns.push(quote! {
+            [ColumnSchema::new(#column, "TEXT", true)]
+        });
+    }
+    let table_schema_impl = main_key.table_schema_impl(&table_name, columns, &[]);
+
+    let selection_query_with_deleted = format!(
+        "SELECT {} FROM {} WHERE {}",
+        marker_col,
+        table_name,
+        main_key.where_clause()
     );
+    let selection_query = match &soft_delete {
+        Some(column) => format!("{} AND {} IS NULL", selection_query_with_deleted, column),
+        None => selection_query_with_deleted.clone(),
+    };
+    let get_body = |selection_query: &str| {
+        quote! {
+            let mut guard = e.lock().await;
+            let tr = guard.a.as_mut().unwrap();
+
+            let marker = sqlx::query_scalar::<_, Option<bool>>(#selection_query)
+                #key_binds
+                .fetch_optional(&mut **tr)
+                .await;
+
+            // A NULL or false column, or no row at all, means the entity does not have the marker
+            match marker {
+                Ok(Some(Some(true))) => Ok(Some(#ident {})),
+                Ok(_) => Ok(None),
+                Err(_) => Err(()),
+            }
+        }
+    };
+    let get_body_with_deleted = get_body(&selection_query_with_deleted);
+    let get_body = get_body(&selection_query);
 
     let update_query = format!(
-        "UPDATE {} SET {} = ? WHERE {} = ?",
-        table_name, marker_col, main_key_field
+        "UPDATE {} SET {} = ? WHERE {}",
+        table_name,
+        marker_col,
+        main_key.where_clause()
     );
 
-    let load_all_query = format!(
-        "SELECT {} FROM {} WHERE {} = ?",
-        main_key_field, table_name, marker_col
+    let load_all_query = Sql::Static(format!(
+        "SELECT {} FROM {}",
+        main_key.columns.join(", "),
+        table_name
+    ));
+
+    let has_marker = match &soft_delete {
+        Some(column) => format!("{} = ? AND {} IS NULL", marker_col, column),
+        None => format!("{} = ?", marker_col),
+    };
+    let load_all_query_impl = get_load_all_query_impl(
+        ast,
+        load_all_query,
+        Some(has_marker),
+        quote!(.bind(true)),
+        &main_key,
     );
 
-    let load_all_query_impl = get_load_all_query_impl(ast, data, load_all_query);
+    let audit_columns = main_key
+        .columns
+        .iter()
+        .chain([&marker_col])
+        .map(|column| ColumnTerm::Column(column.clone()))
+        .collect::<Vec<ColumnTerm>>();
+    let audit_impl = audit_impl(&table_name, &audit_columns, &main_key.where_clause());
+
+    let from_row_impl = get_from_row_impl(ast, &column_fields);
 
     let gen = quote! {
         use bevy_erm_core::*;
 
+        #from_row_impl
+
         #[async_trait]
         impl ComponentMapper for #ident {
             type Component = #ident;
@@ -106,41 +411,73 @@ fn marker_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
             async fn get<'c>(
                 e: &mut Self::Executor,
                 db_entity: &DatabaseEntityId,
-            ) -> Result<Self::Component, ()> {
-                let mut guard = e.lock().await;
+            ) -> Result<Option<Self::Component>, ()> {
+                #get_body
+            }
+
+            async fn update_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                Self::insert_component(tr, db_entity, component).await
+            }
+
+            async fn insert_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                let mut guard = tr.lock().await;
                 let tr = guard.a.as_mut().unwrap();
 
-                let marker_bool = sqlx::query(#selection_query)
-                    .bind(db_entity)
-                    .fetch_one(&mut **tr)
+                let r = sqlx::query(#update_query)
+                    .bind(true)
+                    #key_binds
+                    .execute(&mut **tr)
                     .await;
 
-                match marker_bool {
-                    Ok(_) => Ok(#ident {}),
+                match r {
+                    Ok(_) => Ok(()),
                     Err(_) => Err(()),
                 }
             }
 
-            async fn update_component<'c>(
+            async fn get_with_deleted<'c>(
+                e: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<Option<Self::Component>, ()> {
+                #get_body_with_deleted
+            }
+
+            // Inserting only sets the marker column of a row written by the other components
+            async fn upsert_component<'c>(
                 tr: &mut Self::Executor,
                 db_entity: &DatabaseEntityId,
                 component: &Self::Component,
             ) -> Result<(), ()> {
-                // Can't really imaging that this is ever called for a marker component
-                Ok(())
+                Self::insert_component(tr, db_entity, component).await
             }
 
-            async fn insert_component<'c>(
+            async fn delete_component<'c>(
                 tr: &mut Self::Executor,
                 db_entity: &DatabaseEntityId,
                 component: &Self::Component,
+                now: std::time::SystemTime,
+            ) -> Result<(), ()> {
+                Self::remove_component(tr, db_entity).await
+            }
+
+            async fn remove_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
             ) -> Result<(), ()> {
                 let mut guard = tr.lock().await;
                 let tr = guard.a.as_mut().unwrap();
 
                 let r = sqlx::query(#update_query)
-                    .bind(true)
-                    .bind(db_entity)
+                    .bind(false)
+                    #key_binds
                     .execute(&mut **tr)
                     .await;
 
@@ -149,6 +486,15 @@ fn marker_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
                     Err(_) => Err(()),
                 }
             }
+
+            #audit_impl
+
+            #key_impl
+
+            // The row is inserted by the other components of the table
+            const INSERTS_ROW: bool = false;
+
+            #table_schema_impl
         }
 
         #load_all_query_impl
@@ -156,102 +502,931 @@ fn marker_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
 
     };
 
-    gen.into()
+    Ok(gen)
+}
+
+// Reads the row as a JSON object of `columns` for the audit log, along with writing its entries
+fn audit_impl(table: &str, columns: &[ColumnTerm], where_clause: &str) -> proc_macro2::TokenStream {
+    let row_json_query = Sql::format(
+        "SELECT json_object({}) FROM {} WHERE {}",
+        &[
+            &join_columns(columns, "'{}', {}", ", "),
+            &Sql::Static(table.to_string()),
+            &Sql::Static(where_clause.to_string()),
+        ],
+    );
+    quote! {
+        async fn row_json<'c>(
+            tr: &mut Self::Executor,
+            db_entity: &DatabaseEntityId,
+        ) -> Result<Option<String>, ()> {
+            audit_row_json(tr, &#row_json_query, db_entity).await
+        }
+
+        async fn write_audit_entry<'c>(
+            tr: &mut Self::Executor,
+            log: &AuditLog,
+            entry: &AuditEntry,
+        ) -> Result<(), ()> {
+            insert_audit_entry(tr, log, entry).await
+        }
+    }
+}
+
+// The table and key column of a marker stored as a row of its own table,
+// given with #[marker_table(table = "admins", key = "user_id")]. The attribute can not be called
+// #[marker] as derive helpers of that name are ambiguous with the builtin #[marker] attribute
+struct MarkerTable {
+    table: String,
+    key: Option<String>,
+}
+
+fn get_marker_table(attr: &syn::Attribute) -> syn::Result<MarkerTable> {
+    let mut table = None;
+    let mut key = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("table") {
+            let value: syn::LitStr = meta.value()?.parse()?;
+            table = Some(value.value());
+            Ok(())
+        } else if meta.path.is_ident("key") {
+            let value: syn::LitStr = meta.value()?.parse()?;
+            key = Some(value.value());
+            Ok(())
+        } else {
+            Err(meta.error("expected #[marker_table(table = \"...\", key = \"...\")]"))
+        }
+    })?;
+
+    let table = table.ok_or_else(|| {
+        syn::Error::new_spanned(
+            attr,
+            "#[marker_table] needs the name of the table the marker rows are stored in",
+        )
+    })?;
+    Ok(MarkerTable { table, key })
 }
 
-fn get_table_name(ast: &DeriveInput) -> String {
-    let table_name_meta = ast
-        .attrs
+// A marker stored as a row in a table of its own, eg. a user is an admin when the `admins` table
+// has a row for them, so adding the marker inserts the row and removing it deletes the row
+fn existence_marker_component(
+    ast: &DeriveInput,
+    data: &DataStruct,
+    attr: &syn::Attribute,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &ast.ident;
+    let MarkerTable { table, key } = get_marker_table(attr)?;
+
+    if let Some(column) = find_attribute(&ast.attrs, "column") {
+        return Err(syn::Error::new_spanned(
+            column,
+            "#[column] can not be used with #[marker_table], the marker is stored as a row not a column",
+        ));
+    }
+
+    let column_fields = get_column_fields(data)?;
+    let mut main_key = get_main_key(ast, &column_fields)?;
+    // The key column of the marker table holds the key of the entity, named like it by default
+    if let Some(key) = key {
+        main_key.columns = vec![key];
+    }
+    let key_binds = main_key.binds();
+    let key_impl = main_key.key_impl();
+    let table_schema_impl =
+        main_key.table_schema_impl(&table, main_key.column_schemas(&column_fields), &[]);
+
+    let key_columns = main_key.columns.join(", ");
+    let where_clause = main_key.where_clause();
+    let selection_query = format!("SELECT 1 FROM {} WHERE {}", table, where_clause);
+    let insert_query = format!(
+        "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+        table,
+        key_columns,
+        vec!["?"; main_key.columns.len()].join(", ")
+    );
+    let delete_query = format!("DELETE FROM {} WHERE {}", table, where_clause);
+
+    let load_all_query = Sql::Static(format!("SELECT {} FROM {}", key_columns, table));
+    let load_all_query_impl =
+        get_load_all_query_impl(ast, load_all_query, None, quote!(), &main_key);
+
+    let audit_columns = main_key
+        .columns
         .iter()
-        .find(|attr| attr.path().is_ident("table_name"))
-        .expect("No table name provided")
-        .clone()
-        .meta;
-    let syn::Meta::NameValue(name_value) = table_name_meta else {
-        panic!("table_name attribute must be a name value pair");
+        .map(|column| ColumnTerm::Column(column.clone()))
+        .collect::<Vec<ColumnTerm>>();
+    let audit_impl = audit_impl(&table, &audit_columns, &where_clause);
+
+    let from_row_impl = get_from_row_impl(ast, &column_fields);
+
+    Ok(quote! {
+        use bevy_erm_core::*;
+
+        #from_row_impl
+
+        #[async_trait]
+        impl ComponentMapper for #ident {
+            type Component = #ident;
+            type Executor = <bevy_erm_core::SqlxSqliteDatabaseResource as DatabaseResource>::Transaction;
+
+            async fn get<'c>(
+                e: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<Option<Self::Component>, ()> {
+                let mut guard = e.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let row = sqlx::query(#selection_query)
+                    #key_binds
+                    .fetch_optional(&mut **tr)
+                    .await;
+
+                match row {
+                    Ok(Some(_)) => Ok(Some(#ident {})),
+                    Ok(None) => Ok(None),
+                    Err(_) => Err(()),
+                }
+            }
+
+            async fn update_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                Self::insert_component(tr, db_entity, component).await
+            }
+
+            // The row may already be there, eg. when the marker is added back in the same request
+            async fn insert_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let r = sqlx::query(#insert_query)
+                    #key_binds
+                    .execute(&mut **tr)
+                    .await;
+
+                match r {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(()),
+                }
+            }
+
+            async fn get_with_deleted<'c>(
+                e: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<Option<Self::Component>, ()> {
+                Self::get(e, db_entity).await
+            }
+
+            // The insert is an INSERT OR IGNORE so already leaves an existing row as it is
+            async fn upsert_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                Self::insert_component(tr, db_entity, component).await
+            }
+
+            async fn delete_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+                now: std::time::SystemTime,
+            ) -> Result<(), ()> {
+                Self::remove_component(tr, db_entity).await
+            }
+
+            async fn remove_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<(), ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let r = sqlx::query(#delete_query)
+                    #key_binds
+                    .execute(&mut **tr)
+                    .await;
+
+                match r {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(()),
+                }
+            }
+
+            #audit_impl
+
+            #key_impl
+
+            #table_schema_impl
+        }
+
+        #load_all_query_impl
+    })
+}
+
+// An enum component is stored in a discriminator column of the table, eg. a `role` column
+// holding which of `Buyer` or `Seller` the user is, and rows where it is NULL do not have the component
+fn enum_component(ast: &DeriveInput, data: &DataEnum) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &ast.ident;
+    let encoding_impl = get_enum_variants(ast, data)?.encoding_impl();
+
+    // The column is the lowercase enum name unless given with #[column = "..."]
+    let column = get_string_attribute(&ast.attrs, "column")?
+        .unwrap_or_else(|| ident.to_string().to_lowercase());
+
+    let table_name = get_table_name(ast)?;
+    let main_key = get_main_key(ast, &[])?;
+    let key_binds = main_key.binds();
+    let key_impl = main_key.key_impl();
+
+    // Rows soft deleted by the other components of the table, given with the same #[soft_delete]
+    // attribute, do not have the component
+    let soft_delete = find_attribute(&ast.attrs, "soft_delete")
+        .map(get_soft_delete_column)
+        .transpose()?;
+
+    let mut columns = main_key.column_schemas(&[]);
+    columns.push(quote! {
+        [ColumnSchema::new(#column, <#ident as SqlColumnType>::SQL_TYPE, true)]
+    });
+    if let Some(column) = &soft_delete {
+        columns.push(quote! {
+            [ColumnSchema::new(#column, "TEXT", true)]
+        });
+    }
+    let table_schema_impl = main_key.table_schema_impl(&table_name, columns, &[]);
+
+    let selection_query_with_deleted = format!(
+        "SELECT {} FROM {} WHERE {}",
+        column,
+        table_name,
+        main_key.where_clause()
+    );
+    let selection_query = match &soft_delete {
+        Some(column) => format!("{} AND {} IS NULL", selection_query_with_deleted, column),
+        None => selection_query_with_deleted.clone(),
     };
+    let get_body = |selection_query: &str| {
+        quote! {
+            use sqlx::Row;
 
-    let syn::Expr::Lit(table_name) = name_value.value else {
-        panic!("table_name attribute must be a string");
+            let mut guard = e.lock().await;
+            let tr = guard.a.as_mut().unwrap();
+
+            let row = sqlx::query(#selection_query)
+                #key_binds
+                .fetch_optional(&mut **tr)
+                .await
+                .map_err(|_| ())?;
+
+            // No row or a NULL column means the entity does not have the component
+            match row {
+                Some(row) => row.try_get::<Option<#ident>, _>(0).map_err(|_| ()),
+                None => Ok(None),
+            }
+        }
     };
+    let get_body_with_deleted = get_body(&selection_query_with_deleted);
+    let get_body = get_body(&selection_query);
 
-    let syn::Lit::Str(table_name) = table_name.lit else {
-        panic!("table_name attribute must be a string");
+    let update_query = format!(
+        "UPDATE {} SET {} = ? WHERE {}",
+        table_name,
+        column,
+        main_key.where_clause()
+    );
+
+    let load_all_query = Sql::Static(format!(
+        "SELECT {}, {} FROM {}",
+        main_key.columns.join(", "),
+        column,
+        table_name
+    ));
+
+    let has_variant = match &soft_delete {
+        Some(soft_delete) => format!("{} IS NOT NULL AND {} IS NULL", column, soft_delete),
+        None => format!("{} IS NOT NULL", column),
     };
+    let load_all_query_impl =
+        get_load_all_query_impl(ast, load_all_query, Some(has_variant), quote!(), &main_key);
+
+    let audit_columns = main_key
+        .columns
+        .iter()
+        .chain([&column])
+        .map(|column| ColumnTerm::Column(column.clone()))
+        .collect::<Vec<ColumnTerm>>();
+    let audit_impl = audit_impl(&table_name, &audit_columns, &main_key.where_clause());
+
+    let gen = quote! {
+        use bevy_erm_core::*;
+
+        #encoding_impl
+
+        impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for #ident {
+            fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+                use sqlx::Row;
+
+                row.try_get(#column)
+            }
+        }
+
+        #[async_trait]
+        impl ComponentMapper for #ident {
+            type Component = #ident;
+            type Executor = <bevy_erm_core::SqlxSqliteDatabaseResource as DatabaseResource>::Transaction;
+
+            async fn get<'c>(
+                e: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<Option<Self::Component>, ()> {
+                #get_body
+            }
+
+            async fn update_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let r = sqlx::query(#update_query)
+                    .bind(component)
+                    #key_binds
+                    .execute(&mut **tr)
+                    .await;
+
+                match r {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(()),
+                }
+            }
+
+            // The row belongs to the other components of the table so only the column is set
+            async fn insert_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                Self::update_component(tr, db_entity, component).await
+            }
+
+            async fn get_with_deleted<'c>(
+                e: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<Option<Self::Component>, ()> {
+                #get_body_with_deleted
+            }
 
-    table_name.value()
+            // Inserting sets the column, replacing the variant stored on an existing row
+            async fn upsert_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+            ) -> Result<(), ()> {
+                Self::insert_component(tr, db_entity, component).await
+            }
+
+            async fn delete_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+                now: std::time::SystemTime,
+            ) -> Result<(), ()> {
+                Self::remove_component(tr, db_entity).await
+            }
+
+            async fn remove_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<(), ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let r = sqlx::query(#update_query)
+                    .bind(None::<#ident>)
+                    #key_binds
+                    .execute(&mut **tr)
+                    .await;
+
+                match r {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(()),
+                }
+            }
+
+            #audit_impl
+
+            #key_impl
+
+            // The row is inserted by the other components of the table
+            const INSERTS_ROW: bool = false;
+
+            #table_schema_impl
+        }
+
+        #load_all_query_impl
+    };
+
+    Ok(gen)
+}
+
+// Decodes the component from a row, reading each field from its column
+// Skipped fields are not in the row so are filled with their Default
+fn get_from_row_impl(ast: &DeriveInput, column_fields: &[ColumnField]) -> proc_macro2::TokenStream {
+    let ident = &ast.ident;
+
+    let loaded = column_fields.iter().filter(|field| field.is_loaded());
+    let loaded_idents = loaded.clone().map(|field| &field.ident);
+    let loaded_values = loaded.map(|field| {
+        let column = &field.column;
+        field.get_value(&quote!(#column))
+    });
+    let skipped_idents = column_fields
+        .iter()
+        .filter(|field| !field.is_loaded())
+        .map(|field| &field.ident);
+
+    quote! {
+        impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for #ident {
+            fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+                use sqlx::Row;
+
+                Ok(#ident {
+                    #(#loaded_idents: #loaded_values,)*
+                    #(#skipped_idents: Default::default(),)*
+                })
+            }
+        }
+    }
+}
+
+fn get_table_name(ast: &DeriveInput) -> syn::Result<String> {
+    get_string_attribute(&ast.attrs, "table_name")?.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &ast.ident,
+            "missing #[table_name = \"...\"] attribute naming the table the component is stored in",
+        )
+    })
+}
+
+fn find_attribute<'a>(attrs: &'a [syn::Attribute], name: &str) -> Option<&'a syn::Attribute> {
+    attrs.iter().find(|attr| attr.path().is_ident(name))
 }
 
-fn get_main_key(_ast: &DeriveInput) -> Ident {
-    syn::parse_str::<Ident>("id").unwrap()
+fn get_string_attribute(attrs: &[syn::Attribute], name: &str) -> syn::Result<Option<String>> {
+    find_attribute(attrs, name)
+        .map(|attr| string_attribute_value(attr, name))
+        .transpose()
 }
 
-fn full_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
+// The columns used as the key of the table
+struct MainKey {
+    columns: Vec<String>,
+    // The fields marked with #[main_key]
+    fields: Vec<Ident>,
+    // The DatabaseKeyType variant of the key column given by #[key_type = "text"]
+    key_type: Ident,
+}
+
+impl MainKey {
+    fn where_clause(&self) -> String {
+        self.columns
+            .iter()
+            .map(|column| format!("{} = ?", column))
+            .collect::<Vec<String>>()
+            .join(" AND ")
+    }
+
+    // Binds each key column from the `db_entity` key
+    fn binds(&self) -> proc_macro2::TokenStream {
+        let indices = 0..self.columns.len();
+        quote! {
+            #(.bind(&db_entity.parts()[#indices]))*
+        }
+    }
+
+    // The key columns, typed by the field stored in them or otherwise by the key type
+    // Like the other columns passed to table_schema_impl each is an iterator of ColumnSchema
+    fn column_schemas(&self, column_fields: &[ColumnField]) -> Vec<proc_macro2::TokenStream> {
+        let key_type = &self.key_type;
+        self.columns
+            .iter()
+            .map(
+                |column| match column_fields.iter().find(|field| &field.column == column) {
+                    Some(field) => {
+                        let ty = &field.ty;
+                        quote! {
+                            [ColumnSchema::new(#column, <#ty as SqlColumnType>::SQL_TYPE, false)]
+                        }
+                    }
+                    None => quote! {
+                        [ColumnSchema::new(#column, DatabaseKeyType::#key_type.sql_type(), false)]
+                    },
+                },
+            )
+            .collect()
+    }
+
+    // `foreign_keys` are the fields marked with #[references = "table"]
+    fn table_schema_impl(
+        &self,
+        table_name: &str,
+        columns: Vec<proc_macro2::TokenStream>,
+        foreign_keys: &[&ColumnField],
+    ) -> proc_macro2::TokenStream {
+        let key_columns = &self.columns;
+        let foreign_key_columns = foreign_keys.iter().map(|field| &field.column);
+        let referenced_tables = foreign_keys
+            .iter()
+            .map(|field| field.references.as_deref().unwrap_or_default());
+        quote! {
+            fn table_schema() -> TableSchema {
+                let mut columns = Vec::new();
+                #(columns.extend(#columns);)*
+
+                TableSchema {
+                    name: #table_name.to_string(),
+                    columns,
+                    primary_key: vec![#(#key_columns.to_string()),*],
+                    foreign_keys: vec![#(ForeignKey::new(#foreign_key_columns, #referenced_tables)),*],
+                }
+            }
+        }
+    }
+
+    // Components with a composite key are keyed by their own fields
+    // so the key of a new component is taken from them, as is a text key held in a #[main_key] field
+    fn key_impl(&self) -> proc_macro2::TokenStream {
+        let key_type = &self.key_type;
+        let key_type_impl = quote! {
+            const KEY_TYPE: DatabaseKeyType = DatabaseKeyType::#key_type;
+        };
+
+        let fields = &self.fields;
+        let key = match fields.as_slice() {
+            [field] if key_type == "Text" => quote!(component.#field.clone().into()),
+            [] | [_] => return key_type_impl,
+            _ => quote! {
+                DatabaseEntityId::Composite(vec![
+                    #(component.#fields.clone().into()),*
+                ])
+            },
+        };
+        quote! {
+            #key_type_impl
+
+            fn key(component: &Self::Component) -> Option<DatabaseEntityId> {
+                Some(#key)
+            }
+        }
+    }
+}
+
+// Fields marked with #[main_key] are the key columns, more than one gives a composite key,
+// otherwise the struct level #[primary_key = "column"] attribute is used
+// and if neither are given it defaults to `id`
+fn get_main_key(ast: &DeriveInput, column_fields: &[ColumnField]) -> syn::Result<MainKey> {
+    let key_fields: Vec<&ColumnField> = column_fields
+        .iter()
+        .filter(|field| field.main_key)
+        .collect();
+
+    let primary_key = get_string_attribute(&ast.attrs, "primary_key")?;
+
+    let columns = match (key_fields.is_empty(), primary_key) {
+        (true, primary_key) => vec![primary_key.unwrap_or_else(|| "id".to_string())],
+        (false, None) => key_fields
+            .iter()
+            .map(|field| field.column.clone())
+            .collect(),
+        (false, Some(_)) => {
+            return Err(syn::Error::new_spanned(
+                find_attribute(&ast.attrs, "primary_key"),
+                "#[primary_key] can not be used together with #[main_key] fields",
+            ))
+        }
+    };
+    let fields = key_fields.iter().map(|field| field.ident.clone()).collect();
+
+    let key_type = match get_string_attribute(&ast.attrs, "key_type")?.as_deref() {
+        None | Some("integer") => format_ident!("Integer"),
+        Some("text") => format_ident!("Text"),
+        Some("uuid") => format_ident!("Uuid"),
+        Some(key_type) => {
+            return Err(syn::Error::new_spanned(
+                find_attribute(&ast.attrs, "key_type"),
+                format!(
+                    "unknown key_type \"{}\", expected one of \"integer\", \"text\" or \"uuid\"",
+                    key_type
+                ),
+            ))
+        }
+    };
+
+    Ok(MainKey {
+        columns,
+        fields,
+        key_type,
+    })
+}
+
+fn full_component(ast: &DeriveInput, data: &DataStruct) -> syn::Result<proc_macro2::TokenStream> {
     // Extract necessary information from the input
     let ident = &ast.ident;
 
-    let table_name = get_table_name(ast);
-
-    // Iterate through the fields of the struct
-    // let main_key_field =
-    //     data.fields.iter().find(
-    //         |field| field.attrs.iter().find(|attr| attr.path().is_ident("main_key")).is_some()).unwrap().clone().ident.unwrap();
+    let table_name = get_table_name(ast)?;
 
-    let main_key_field = get_main_key(ast);
+    let column_fields = get_column_fields(data)?;
+    let main_key = get_main_key(ast, &column_fields)?;
+    let key_binds = main_key.binds();
+    let key_impl = main_key.key_impl();
 
-    let field_names: Vec<String> = data
-        .fields
+    // The key fields are read by the select but are bound from the database entity
+    // so are excluded from the remaining columns
+    let data_fields: Vec<&ColumnField> = column_fields
+        .iter()
+        .filter(|field| !field.main_key && !main_key.columns.contains(&field.column))
+        .collect();
+    let loaded_fields: Vec<&ColumnField> = data_fields
+        .iter()
+        .copied()
+        .filter(|field| field.is_loaded())
+        .collect();
+    let written_fields: Vec<&ColumnField> = data_fields
         .iter()
-        .filter(|field| field.ident != Some(main_key_field.clone()))
-        .map(|field| field.ident.clone().unwrap().to_string())
+        .copied()
+        .filter(|field| field.is_written())
         .collect();
 
+    let table = Sql::Static(table_name.clone());
+    let where_clause = Sql::Static(main_key.where_clause());
 
     // select query
-    let selection_terms = field_names.join(", ");
-    let selection_query = format!(
-        "SELECT {}, {} FROM {} WHERE {} = ?",
-        main_key_field, selection_terms, table_name, main_key_field
+    let selection_columns = main_key
+        .columns
+        .iter()
+        .map(|column| ColumnTerm::Column(column.clone()))
+        .chain(loaded_fields.iter().map(|field| field.column_term()))
+        .collect::<Vec<ColumnTerm>>();
+    let selection_terms = join_columns(&selection_columns, "{}", ", ");
+    let selection_query_with_deleted = Sql::format(
+        "SELECT {} FROM {} WHERE {}",
+        &[&selection_terms, &table, &where_clause],
     );
 
-    let update_terms = field_names.join(" = ?, ");
-    let update_query = format!(
-        "UPDATE {} SET {} = ? WHERE {} = ?",
-        table_name, update_terms, main_key_field
+    // Soft deleted rows have a timestamp in the column and are left out of every load
+    // unless asked for with WithDeleted
+    let soft_delete = find_attribute(&ast.attrs, "soft_delete")
+        .map(get_soft_delete_column)
+        .transpose()?;
+    let not_deleted = soft_delete
+        .as_ref()
+        .map(|column| format!("{} IS NULL", column));
+    let selection_query = match &not_deleted {
+        Some(not_deleted) => Sql::format(
+            "{} AND {}",
+            &[
+                &selection_query_with_deleted,
+                &Sql::Static(not_deleted.clone()),
+            ],
+        ),
+        None => selection_query_with_deleted.clone(),
+    };
+
+    let update_terms = join_columns(
+        &written_fields
+            .iter()
+            .map(|field| field.column_term())
+            .collect::<Vec<_>>(),
+        "{} = ?",
+        ", ",
     );
+    let update_query = Sql::format(
+        "UPDATE {} SET {} WHERE {}",
+        &[&table, &update_terms, &where_clause],
+    );
+
+    // The deletion time comes from the Clock like the #[created_at] and #[updated_at] fields
+    let (delete_query, delete_binds) = match &soft_delete {
+        Some(column) => (
+            format!(
+                "UPDATE {} SET {} = ? WHERE {}",
+                table_name,
+                column,
+                main_key.where_clause()
+            ),
+            quote!(.bind(String::from_system_time(now))),
+        ),
+        None => (
+            format!(
+                "DELETE FROM {} WHERE {}",
+                table_name,
+                main_key.where_clause()
+            ),
+            quote!(),
+        ),
+    };
 
-    let binds = field_names
+    let binds = written_fields
         .iter()
-        .filter(|field| main_key_field != field.as_str())
-        .map(|field| format_ident!("{}", field));
+        .map(|field| field.bind_call(&quote!(component)));
 
     let binds = quote! {
-        #(.bind(component.#binds.clone()))*
+        #(#binds)*
     };
 
-    let load_all_query = format!(
-        "SELECT {}, {} FROM {}",
-        main_key_field, selection_terms, table_name
-    );
+    // Nothing to update when every column is part of the key or read only
+    let (update_component, update_components) = if written_fields.is_empty() {
+        (quote!(Ok(())), quote!(Ok(())))
+    } else {
+        let update_component = quote! {
+            let mut guard = tr.lock().await;
+            let tr = guard.a.as_mut().unwrap();
+
+            let r = sqlx::query(&#update_query)
+                #binds
+                #key_binds
+                .execute(&mut **tr)
+                .await;
+
+            match r {
+                Ok(_) => Ok(()),
+                Err(_) => Err(()),
+            }
+        };
+        // Every row runs the same statement, which sqlx prepares once for the connection
+        let update_components = quote! {
+            let mut guard = tr.lock().await;
+            let tr = guard.a.as_mut().unwrap();
+            let update_query: &str = &#update_query;
 
-    let load_all_query_impl = get_load_all_query_impl(ast, data, load_all_query);
+            for (db_entity, component) in components {
+                sqlx::query(update_query)
+                    #binds
+                    #key_binds
+                    .execute(&mut **tr)
+                    .await
+                    .map_err(|_| ())?;
+            }
+            Ok(())
+        };
+        (update_component, update_components)
+    };
 
+    let load_all_query = Sql::format("SELECT {} FROM {}", &[&selection_terms, &table]);
 
-    let insert_terms = field_names.join(", ");
-    let question_marks = field_names
+    // Read only columns are filled by the database so may be left out of inserts
+    let mut columns = main_key.column_schemas(&column_fields);
+    columns.extend(loaded_fields.iter().map(|field| {
+        let column = &field.column;
+        let ty = &field.ty;
+        let (sql_type, nullable) = field.sql_type();
+        let readonly = field.readonly;
+        match &field.flatten {
+            Some(prefix) => quote! {
+                <#ty as Embedded>::column_schemas(#prefix)
+            },
+            None => quote! {
+                [ColumnSchema::new(#column, #sql_type, #nullable || #readonly)]
+            },
+        }
+    }));
+    if let Some(column) = &soft_delete {
+        columns.push(quote! {
+            [ColumnSchema::new(#column, "TEXT", true)]
+        });
+    }
+    let foreign_keys = column_fields
         .iter()
-        .map(|_| "?")
-        .collect::<Vec<&str>>()
-        .join(", ");
-    let insert_query = format!(
-        "INSERT INTO {} ({}, {}) VALUES (?,{})",
-        table_name, main_key_field, insert_terms, question_marks
+        .filter(|field| field.references.is_some())
+        .collect::<Vec<&ColumnField>>();
+    let table_schema_impl = main_key.table_schema_impl(&table_name, columns, &foreign_keys);
+
+    // Soft deleted rows are still read by the audit log so their deletion is recorded
+    let mut audit_columns = selection_columns;
+    audit_columns.extend(
+        soft_delete
+            .iter()
+            .map(|column| ColumnTerm::Column(column.clone())),
+    );
+    let audit_impl = audit_impl(&table_name, &audit_columns, &main_key.where_clause());
+
+    let mut load_all_query_impl = get_load_all_query_impl(
+        ast,
+        load_all_query.clone(),
+        not_deleted.clone(),
+        quote!(),
+        &main_key,
     );
+    if soft_delete.is_some() {
+        load_all_query_impl.extend(get_with_deleted_query_impl(ast, load_all_query));
+    }
+    let insert_terms = main_key
+        .columns
+        .iter()
+        .map(|column| ColumnTerm::Column(column.clone()))
+        .chain(written_fields.iter().map(|field| field.column_term()))
+        .collect::<Vec<ColumnTerm>>();
+    let key_columns = main_key.columns.join(", ");
+    // Rows whose key is already in the table get the written columns of the component instead,
+    // keeping the time they were created
+    let upserted_terms = written_fields
+        .iter()
+        .filter(|field| !field.created_at)
+        .map(|field| field.column_term())
+        // The inserted row is never deleted, so upserting onto a soft deleted row restores it
+        // rather than leaving the written row hidden
+        .chain(
+            soft_delete
+                .iter()
+                .map(|column| ColumnTerm::Column(column.clone())),
+        )
+        .collect::<Vec<ColumnTerm>>();
+    let upsert_clause = if upserted_terms.is_empty() {
+        Sql::Static(format!(" ON CONFLICT ({}) DO NOTHING", key_columns))
+    } else {
+        Sql::format(
+            &format!(" ON CONFLICT ({}) DO UPDATE SET {{}}", key_columns),
+            &[&join_columns(&upserted_terms, "{} = excluded.{}", ", ")],
+        )
+    };
+    // With #[upsert] every insert of the component is an upsert
+    let insert_suffix = match find_attribute(&ast.attrs, "upsert") {
+        Some(attr) => {
+            require_flag(attr, "upsert")?;
+            upsert_clause.clone()
+        }
+        None => Sql::Static(String::new()),
+    };
+    let insert_query = Sql::format(
+        "INSERT INTO {} ({}) VALUES ({}){}",
+        &[
+            &table,
+            &join_columns(&insert_terms, "{}", ", "),
+            &join_columns(&insert_terms, "?", ", "),
+            &insert_suffix,
+        ],
+    );
+    let upsert_query = Sql::format(
+        "INSERT INTO {} ({}) VALUES ({}){}",
+        &[
+            &table,
+            &join_columns(&insert_terms, "{}", ", "),
+            &join_columns(&insert_terms, "?", ", "),
+            &upsert_clause,
+        ],
+    );
+
+    // The flush inserts many rows at once, repeating the row after the header
+    let insert_header = Sql::format(
+        "INSERT INTO {} ({}) VALUES ",
+        &[&table, &join_columns(&insert_terms, "{}", ", ")],
+    );
+    let insert_row = Sql::format("({})", &[&join_columns(&insert_terms, "?", ", ")]);
+
+    let created_fields = data_fields
+        .iter()
+        .filter(|field| field.created_at || field.updated_at)
+        .map(|field| &field.ident);
+    let updated_fields = data_fields
+        .iter()
+        .filter(|field| field.updated_at)
+        .map(|field| &field.ident);
+    let timestamps_impl = if data_fields
+        .iter()
+        .any(|field| field.created_at || field.updated_at)
+    {
+        quote! {
+            const TIMESTAMPED: bool = true;
+
+            fn set_created_at(component: &mut Self::Component, now: std::time::SystemTime) {
+                #(component.#created_fields = Timestamp::from_system_time(now);)*
+            }
+
+            fn set_updated_at(component: &mut Self::Component, now: std::time::SystemTime) {
+                #(component.#updated_fields = Timestamp::from_system_time(now);)*
+            }
+        }
+    } else {
+        quote!()
+    };
 
     // Generate the implementation of the IndexInfo trait
+    let from_row_impl = get_from_row_impl(ast, &column_fields);
+
     let gen = quote! {
         use bevy_erm_core::*;
 
+        #from_row_impl
+
         #[async_trait]
         impl ComponentMapper for #ident {
             type Component = #ident;
@@ -260,30 +1435,50 @@ fn full_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
             async fn get<'c>(
                 e: &mut Self::Executor,
                 db_entity: &DatabaseEntityId,
-            ) -> Result<Self::Component, ()> {
+            ) -> Result<Option<Self::Component>, ()> {
                 let mut guard = e.lock().await;
                 let tr = guard.a.as_mut().unwrap();
 
-                let items = sqlx::query_as::<_, #ident>(#selection_query)
-                    .bind(db_entity)
-                    .fetch_one(&mut **tr)
+                sqlx::query_as::<_, #ident>(&#selection_query)
+                    #key_binds
+                    .fetch_optional(&mut **tr)
                     .await
-                    .unwrap();
+                    .map_err(|_| ())
+            }
+
+            async fn get_with_deleted<'c>(
+                e: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<Option<Self::Component>, ()> {
+                let mut guard = e.lock().await;
+                let tr = guard.a.as_mut().unwrap();
 
-                Ok(items)
+                sqlx::query_as::<_, #ident>(&#selection_query_with_deleted)
+                    #key_binds
+                    .fetch_optional(&mut **tr)
+                    .await
+                    .map_err(|_| ())
             }
 
             async fn update_component<'c>(
                 tr: &mut Self::Executor,
                 db_entity: &DatabaseEntityId,
                 component: &Self::Component,
+            ) -> Result<(), ()> {
+                #update_component
+            }
+
+            async fn insert_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
             ) -> Result<(), ()> {
                 let mut guard = tr.lock().await;
                 let tr = guard.a.as_mut().unwrap();
 
-                let r = sqlx::query(#update_query)
+                let r = sqlx::query(&#insert_query)
+                    #key_binds
                     #binds
-                    .bind(db_entity)
                     .execute(&mut **tr)
                     .await;
 
@@ -293,7 +1488,42 @@ fn full_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
                 }
             }
 
-            async fn insert_component<'c>(
+            async fn insert_components<'c>(
+                tr: &mut Self::Executor,
+                components: &[(&DatabaseEntityId, &Self::Component)],
+            ) -> Result<(), ()>
+            where
+                Self::Component: Sync,
+            {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+                let insert_header: &str = &#insert_header;
+                let insert_row: &str = &#insert_row;
+                let insert_suffix: &str = &#insert_suffix;
+
+                // Each row binds a parameter per column, split the rows so no statement binds
+                // more than SQLite allows
+                let rows_per_statement =
+                    (SQLITE_MAX_BIND_PARAMETERS / insert_row.matches('?').count()).max(1);
+                for rows in components.chunks(rows_per_statement) {
+                    let insert_query = format!(
+                        "{}{}{}",
+                        insert_header,
+                        vec![insert_row; rows.len()].join(", "),
+                        insert_suffix
+                    );
+                    let mut query = sqlx::query(&insert_query);
+                    for (db_entity, component) in rows {
+                        query = query
+                            #key_binds
+                            #binds;
+                    }
+                    query.execute(&mut **tr).await.map_err(|_| ())?;
+                }
+                Ok(())
+            }
+
+            async fn upsert_component<'c>(
                 tr: &mut Self::Executor,
                 db_entity: &DatabaseEntityId,
                 component: &Self::Component,
@@ -301,8 +1531,8 @@ fn full_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
                 let mut guard = tr.lock().await;
                 let tr = guard.a.as_mut().unwrap();
 
-                let r = sqlx::query(#insert_query)
-                    .bind(db_entity)
+                let r = sqlx::query(&#upsert_query)
+                    #key_binds
                     #binds
                     .execute(&mut **tr)
                     .await;
@@ -312,6 +1542,53 @@ fn full_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
                     Err(_) => Err(()),
                 }
             }
+
+            async fn update_components<'c>(
+                tr: &mut Self::Executor,
+                components: &[(&DatabaseEntityId, &Self::Component)],
+            ) -> Result<(), ()>
+            where
+                Self::Component: Sync,
+            {
+                #update_components
+            }
+
+            async fn delete_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+                component: &Self::Component,
+                now: std::time::SystemTime,
+            ) -> Result<(), ()> {
+                let mut guard = tr.lock().await;
+                let tr = guard.a.as_mut().unwrap();
+
+                let r = sqlx::query(#delete_query)
+                    #delete_binds
+                    #key_binds
+                    .execute(&mut **tr)
+                    .await;
+
+                match r {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(()),
+                }
+            }
+
+            // The row stays as removing the component is not deleting the entity
+            async fn remove_component<'c>(
+                tr: &mut Self::Executor,
+                db_entity: &DatabaseEntityId,
+            ) -> Result<(), ()> {
+                Ok(())
+            }
+
+            #timestamps_impl
+
+            #audit_impl
+
+            #key_impl
+
+            #table_schema_impl
         }
 
         #load_all_query_impl
@@ -319,5 +1596,5 @@ fn full_component(ast: &DeriveInput, data: &DataStruct) -> TokenStream {
     };
 
     // Convert the generated code into a token stream and return it
-    gen.into()
+    Ok(gen)
 }
@@ -0,0 +1,50 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, LitStr};
+
+// Embeds the `.sql` files of a directory, relative to the crate root, as migrations
+// The files are included with include_str! so changes to them recompile the crate
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    let dir_literal = parse_macro_input!(input as LitStr);
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let dir = std::path::Path::new(&manifest_dir).join(dir_literal.value());
+
+    let files = std::fs::read_dir(&dir).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+    });
+    let mut files = match files {
+        Ok(files) => files
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|extension| extension == "sql"))
+            .collect::<Vec<_>>(),
+        Err(error) => {
+            return syn::Error::new_spanned(
+                dir_literal,
+                format!(
+                    "failed to read migrations directory {}: {}",
+                    dir.display(),
+                    error
+                ),
+            )
+            .into_compile_error()
+            .into()
+        }
+    };
+    files.sort();
+
+    let file_names = files
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().to_string());
+    let paths = files.iter().map(|path| path.to_string_lossy().to_string());
+
+    let gen = quote! {
+        bevy_erm_core::Migrations::from_files(vec![
+            #((#file_names, include_str!(#paths))),*
+        ])
+    };
+
+    gen.into()
+}
@@ -0,0 +1,108 @@
+use quote::{quote, ToTokens};
+
+// Generated sql, written out at compile time unless it lists the columns of a #[flatten] field,
+// as those are only known once the embedded type has been compiled
+#[derive(Clone)]
+pub enum Sql {
+    Static(String),
+    // The template of a format! along with the expressions filling in its {}
+    Dynamic {
+        template: String,
+        args: Vec<proc_macro2::TokenStream>,
+    },
+}
+
+fn escape(sql: &str) -> String {
+    sql.replace('{', "{{").replace('}', "}}")
+}
+
+impl Sql {
+    // Replaces each {} in the template with the next piece
+    pub fn format(template: &str, pieces: &[&Sql]) -> Sql {
+        let mut parts = template.split("{}");
+        let first = parts.next().unwrap_or_default();
+        let mut static_sql = first.to_string();
+        let mut dynamic_template = escape(first);
+        let mut args = Vec::new();
+
+        for (piece, part) in pieces.iter().zip(parts) {
+            match piece {
+                Sql::Static(sql) => {
+                    static_sql.push_str(sql);
+                    dynamic_template.push_str(&escape(sql));
+                }
+                Sql::Dynamic {
+                    template,
+                    args: piece_args,
+                } => {
+                    dynamic_template.push_str(template);
+                    args.extend(piece_args.iter().cloned());
+                }
+            }
+            static_sql.push_str(part);
+            dynamic_template.push_str(&escape(part));
+        }
+
+        if args.is_empty() {
+            Sql::Static(static_sql)
+        } else {
+            Sql::Dynamic {
+                template: dynamic_template,
+                args,
+            }
+        }
+    }
+}
+
+impl ToTokens for Sql {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        match self {
+            Sql::Static(sql) => sql.to_tokens(tokens),
+            Sql::Dynamic { template, args } => {
+                quote!(format!(#template, #(#args),*)).to_tokens(tokens)
+            }
+        }
+    }
+}
+
+// A column of the table, or the columns of an embedded value object
+pub enum ColumnTerm {
+    Column(String),
+    Flatten { ty: Box<syn::Type>, prefix: String },
+}
+
+// Joins the columns with the separator after formatting each with the template, eg. "{} = ?"
+pub fn join_columns(terms: &[ColumnTerm], template: &str, separator: &str) -> Sql {
+    let static_columns = terms
+        .iter()
+        .map(|term| match term {
+            ColumnTerm::Column(column) => Some(template.replace("{}", column)),
+            ColumnTerm::Flatten { .. } => None,
+        })
+        .collect::<Option<Vec<String>>>();
+    if let Some(columns) = static_columns {
+        return Sql::Static(columns.join(separator));
+    }
+
+    let pushes = terms.iter().map(|term| match term {
+        ColumnTerm::Column(column) => quote! {
+            columns.push(#column.to_string());
+        },
+        ColumnTerm::Flatten { ty, prefix } => quote! {
+            columns.extend(<#ty as bevy_erm_core::Embedded>::columns(#prefix));
+        },
+    });
+
+    Sql::Dynamic {
+        template: "{}".to_string(),
+        args: vec![quote! {{
+            let mut columns: Vec<String> = Vec::new();
+            #(#pushes)*
+            columns
+                .iter()
+                .map(|column| #template.replace("{}", column))
+                .collect::<Vec<String>>()
+                .join(#separator)
+        }}],
+    }
+}
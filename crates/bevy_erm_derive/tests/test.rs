@@ -4,7 +4,6 @@ mod tests {
     use async_trait::async_trait;
     use bevy_erm_core::ComponentMapper;
     use bevy_erm_derive::DBQueryDerive;
-    use sqlx::prelude::FromRow;
 
     use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
 
@@ -12,16 +11,52 @@ mod tests {
         fn easy(&self) -> i32;
     }
 
-    #[derive(DBQueryDerive, FromRow)]
+    #[derive(DBQueryDerive)]
     #[table_name = "test"]
     #[allow(dead_code)]
     struct Test {
+        #[main_key]
         id: i32,
         name: String,
         price: f32,
     }
 
-    #[derive(DBQueryDerive, FromRow)]
+    #[derive(DBQueryDerive)]
     #[table_name = "test"]
     struct MarkerTest {}
+
+    #[derive(DBQueryDerive)]
+    #[table_name = "test"]
+    #[primary_key = "test_id"]
+    #[allow(dead_code)]
+    struct PrimaryKeyTest {
+        name: String,
+    }
+
+    // Derived components can implement other traits of the app
+    impl Easy for Test {
+        fn easy(&self) -> i32 {
+            self.id
+        }
+    }
+
+    #[test]
+    fn derived_components_implement_other_traits() {
+        let test = Test {
+            id: 3,
+            name: "test".to_string(),
+            price: 1.0,
+        };
+        assert_eq!(test.easy(), 3);
+    }
+
+    // Markers are keyed by the entity they are on rather than by a field
+    #[test]
+    fn markers_are_keyed_by_their_entity() {
+        assert_eq!(
+            MarkerTest::table_schema().primary_key,
+            vec!["id".to_string()]
+        );
+        assert_eq!(MarkerTest::key(&MarkerTest {}), None);
+    }
 }
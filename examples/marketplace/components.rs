@@ -5,32 +5,35 @@ use bevy_erm_derive::DBQueryDerive;
 
 use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
 
-#[derive(Component, Debug, Default, Clone, sqlx::FromRow, DBQueryDerive)]
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
 #[table_name = "users"]
 pub struct User {
     #[allow(dead_code)]
     pub name: String,
 }
 
-#[derive(Component, Debug, Default, Clone, sqlx::FromRow, DBQueryDerive)]
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
 #[table_name = "users"]
 pub struct Seller {}
 
-#[derive(Component, Debug, Default, Clone, sqlx::FromRow, DBQueryDerive)]
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
 #[table_name = "users"]
 pub struct Buyer {}
 
-#[derive(Component, Debug, Default, Clone, DBQueryDerive, sqlx::FromRow)]
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
 #[table_name = "items"]
 pub struct MarketItem {
+    #[references = "users"]
     pub seller_id: DatabaseEntityId,
     pub name: String,
     pub price: i32,
 }
 
-#[derive(Component, Debug, Default, Clone, DBQueryDerive, sqlx::FromRow)]
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
 #[table_name = "purchased_items"]
 pub struct PurchasedItem {
+    #[references = "items"]
     pub item: DatabaseEntityId,
+    #[references = "users"]
     pub buyer: DatabaseEntityId,
 }
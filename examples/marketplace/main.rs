@@ -20,29 +20,26 @@ pub struct MarketplacePlugin;
 impl Plugin for MarketplacePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EntityRelationMapperPlugin)
-            .add_systems(
-                PostUpdate,
-                flush_component_to_db::<
-                    (
-                        Option<&User>,
-                        Option<&PurchasedItem>,
-                        Option<&Buyer>,
-                        Option<&Seller>,
-                        Option<&MarketItem>,
-                    ),
-                    SqlxSqliteDatabaseResource,
-                >,
-            )
+            .register_db_component::<User>()
+            .register_db_component::<PurchasedItem>()
+            .register_db_component::<Buyer>()
+            .register_db_component::<Seller>()
+            .register_db_component::<MarketItem>()
             .add_event::<Purchase>()
             .add_event::<Sell>()
             .add_event::<GetSellerItems>()
             .add_event::<PurchaseResponse>()
             .add_event::<PrintTable>()
             .init_resource::<WebServer>()
-            .add_systems(Startup, create_tables)
+            // The tables need to exist before the plugin validates them
+            .add_systems(
+                PreStartup,
+                create_tables.after(run_migrations).before(validate_schema),
+            )
             .add_systems(PreUpdate, poll_webserver_for_requests)
             .add_systems(PreUpdate, should_exit)
             .add_systems(Update, purchase_system)
+            .add_systems(Update, (sell_system, get_seller_items_system))
             .add_systems(Update, flush_purchase.after(purchase_system))
             .add_systems(PostUpdate, print_items_table)
             .add_systems(PostUpdate, print_users_table)
@@ -50,10 +47,40 @@ impl Plugin for MarketplacePlugin {
     }
 }
 
+// Runs the marketplace, or writes the migration bringing a database in line with its components
+//
+// cargo run --example marketplace
+// cargo run --example marketplace -- schema-diff sqlite:market.db?mode=rwc migrations "add market items"
 #[tokio::main]
 async fn main() {
-    App::new()
-        .set_runner(runner)
-        .add_plugins(MarketplacePlugin)
-        .run();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.as_slice() {
+        [] => {
+            App::new()
+                .set_runner(runner)
+                .add_plugins(MarketplacePlugin)
+                .run();
+        }
+        [command, url, migrations_dir, description] if command == "schema-diff" => {
+            // The app is only built, running it would refuse to start with the outdated schema
+            let mut app = App::new();
+            app.insert_resource(SqlxSqliteDatabaseResource::connect(url).unwrap())
+                .add_plugins(MarketplacePlugin);
+
+            match EntityRelationMapperPlugin::write_schema_migration(
+                &app,
+                migrations_dir,
+                description,
+            )
+            .unwrap()
+            {
+                Some(path) => println!("Wrote {}", path.display()),
+                None => println!("The database is up to date"),
+            }
+        }
+        _ => {
+            eprintln!("usage: marketplace [schema-diff <database url> <migrations directory> <description>]");
+            std::process::exit(2);
+        }
+    }
 }
@@ -20,17 +20,17 @@ pub fn purchase_system(
         println!("Processing purchase events");
         for purchase in purchases.read() {
             let item = item_query
-                .get(&(purchase.item, purchase.request))
+                .get(&(purchase.item.clone(), purchase.request))
                 .await
                 .unwrap();
             let seller_name = seller_query
-                .get(&(item.seller_id, purchase.request))
+                .get(&(item.seller_id.clone(), purchase.request))
                 .await
                 .unwrap()
                 .name
                 .clone();
             let buyer_name = purchaser_query
-                .get(&(purchase.purchaser, purchase.request))
+                .get(&(purchase.purchaser.clone(), purchase.request))
                 .await
                 .unwrap()
                 .name
@@ -40,8 +40,8 @@ pub fn purchase_system(
                 buyer_name, item.name, seller_name
             );
             let purchased_item = PurchasedItem {
-                item: purchase.item,
-                buyer: purchase.purchaser,
+                item: purchase.item.clone(),
+                buyer: purchase.purchaser.clone(),
             };
 
             db_query_purchased
@@ -57,8 +57,49 @@ pub fn purchase_system(
     });
 }
 
-pub fn create_tables(db: Res<SqlxSqliteDatabaseResource>, _print_tables: EventWriter<PrintTable>) {
+// Puts the item on the market under the seller
+pub fn sell_system(mut sells: EventReader<Sell>, items: DatabaseQuery<&mut MarketItem>) {
+    block_on(async {
+        for sell in sells.read() {
+            let mut item = items.get_mut(&sell.item).await.unwrap();
+            item.seller_id = sell.seller.id.clone();
+            println!("\t{:} is now sold by {:}", item.name, sell.seller.id);
+        }
+    });
+}
+
+pub fn get_seller_items_system(
+    mut get_seller_items: EventReader<GetSellerItems>,
+    items: DatabaseQuery<&MarketItem>,
+) {
+    block_on(async {
+        for get_seller_items in get_seller_items.read() {
+            let seller = &get_seller_items.seller;
+            let names = items
+                .load_components::<&MarketItem, _>(
+                    seller.request,
+                    MarketItemQueryLoadAll(seller.request),
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|item| item.seller_id == seller.id)
+                .map(|item| item.name.clone())
+                .collect::<Vec<_>>();
+            println!("\tSeller {:} sells {:?}", seller.id, names);
+        }
+    });
+}
+
+// Creates the tables of the components registered with register_db_component
+pub fn create_tables(
+    db: Res<SqlxSqliteDatabaseResource>,
+    registered: Res<SchemaValidation>,
+    _print_tables: EventWriter<PrintTable>,
+) {
     println!("Creating tables");
+    registered.create_tables(&db).unwrap();
+
     let request = db.start_new_transaction();
     block_on(async {
         // let db_handle = db.get_connection();
@@ -68,27 +109,6 @@ pub fn create_tables(db: Res<SqlxSqliteDatabaseResource>, _print_tables: EventWr
         let mut guard = conn.lock().await;
         let tr = guard.a.as_mut().unwrap();
 
-        // create the tables
-        // market items table
-        sqlx::query("CREATE TABLE items (id INTEGER PRIMARY KEY, seller_id INTEGER, name TEXT, price INTEGER)")
-            .execute(&mut **tr).await.unwrap();
-
-        // purchased items table
-        sqlx::query(
-            "CREATE TABLE purchased_items (id INTEGER PRIMARY KEY, item INTEGER, buyer INTEGER)",
-        )
-        .execute(&mut **tr)
-        .await
-        .unwrap();
-
-        // users table
-        sqlx::query(
-            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, buyer BOOLEAN, seller BOOLEAN)",
-        )
-        .execute(&mut **tr)
-        .await
-        .unwrap();
-
         // populate one buyer and one seller
         sqlx::query(
             "INSERT INTO users (id, name, buyer, seller) VALUES (?, 'Bob The Buyer', 1, 0)",
@@ -179,7 +199,6 @@ pub fn print_users_table(
     mut print_table_events: EventReader<PrintTable>,
 ) {
     block_on(async {
-
         for print_table in print_table_events.read() {
             let users = {
                 let users = users
@@ -265,8 +284,8 @@ pub fn poll_webserver_for_requests(
     while webserver.is_messages_to_send() {
         if let Some(request) = db.try_start_new_transaction() {
             let purchase_event = Purchase {
-                purchaser: DatabaseEntityId(PURCHASER_ID),
-                item: DatabaseEntityId(MARKET_ITEM_ID),
+                purchaser: DatabaseEntityId::Integer(PURCHASER_ID),
+                item: DatabaseEntityId::Integer(MARKET_ITEM_ID),
                 request,
             };
 
@@ -0,0 +1,25 @@
+// Generates DBQueryDerive components for every table of an existing SQLite database
+//
+// bevy_erm_codegen sqlite:app.db [components.rs]
+//
+// Writes the components to the file if one is given, otherwise prints them
+
+use bevy_erm::codegen::generate_components;
+use bevy_erm::SqlxSqliteDatabaseResource;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(url) = args.next() else {
+        eprintln!("usage: bevy_erm_codegen <database url> [output file]");
+        std::process::exit(2);
+    };
+
+    let db = SqlxSqliteDatabaseResource::connect(&url).unwrap();
+    let source = generate_components(&db).unwrap();
+
+    match args.next() {
+        Some(path) => std::fs::write(path, source).unwrap(),
+        None => print!("{}", source),
+    }
+}
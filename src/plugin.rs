@@ -1,10 +1,271 @@
+use std::any::TypeId;
+use std::path::{Path, PathBuf};
+
 use crate::*;
+use bevy_ecs::event::ManualEventReader;
+use bevy_ecs::prelude::*;
+use bevy_utils::HashSet;
+use futures::executor::block_on;
 
 pub struct EntityRelationMapperPlugin;
 
 impl Plugin for EntityRelationMapperPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<FlushEvent>()
-            .init_resource::<SqlxSqliteDatabaseResource>();
+            .init_resource::<SqlxSqliteDatabaseResource>()
+            .init_resource::<FlushPipeline>()
+            .init_resource::<Clock>()
+            .add_systems(
+                PreStartup,
+                (
+                    order_flush_pipeline,
+                    run_migrations,
+                    create_audit_table,
+                    validate_schema,
+                )
+                    .chain(),
+            )
+            .add_systems(PostUpdate, flush_registered_components);
+    }
+}
+
+// Applies the Migrations resource, if there is one, before any other startup systems run
+// The app panics rather than run against a database it can not migrate
+pub fn run_migrations(db: Res<SqlxSqliteDatabaseResource>, migrations: Option<Res<Migrations>>) {
+    let Some(migrations) = migrations else {
+        return;
+    };
+
+    if let Err(error) = migrations.run(&db) {
+        panic!("Failed to migrate the database: {}", error);
+    }
+}
+
+// Creates the table of the AuditLog resource, if there is one, unless a migration already has
+// The app panics rather than fail on the first audited write
+pub fn create_audit_table(db: Res<SqlxSqliteDatabaseResource>, audit_log: Option<Res<AuditLog>>) {
+    let Some(audit_log) = audit_log else {
+        return;
+    };
+
+    let create_table = audit_log.table_schema().create_table_sql();
+    if let Err(error) = block_on(sqlx::query(&create_table).execute(&db.pool())) {
+        panic!("Failed to create the audit table: {}", error);
+    }
+}
+
+// Checks the tables of the components in the SchemaValidation resource, if there is one,
+// so a missing or mistyped column is found before any request is handled
+pub fn validate_schema(
+    db: Res<SqlxSqliteDatabaseResource>,
+    schema_validation: Option<Res<SchemaValidation>>,
+) {
+    let Some(schema_validation) = schema_validation else {
+        return;
+    };
+
+    if let Err(error) = schema_validation.validate(&db) {
+        panic!("{}", error);
+    }
+}
+
+impl EntityRelationMapperPlugin {
+    // Creates the tables of the given component, or tuple of components, if they do not exist yet
+    // Components mapped to the same table are merged into one table
+    // See SchemaValidation::create_tables for the tables of the registered components
+    pub fn create_schema<T: TableSchemas>(
+        db: &SqlxSqliteDatabaseResource,
+    ) -> Result<(), sqlx::Error> {
+        SchemaValidation::default()
+            .register::<T>()
+            .create_tables(db)
+    }
+
+    // Writes the statements that bring the app's database in line with its registered components
+    // as the next migration in the directory, after applying the migrations already in it, and
+    // returns its path or None when the database is up to date
+    // Call it on an app that has been built but not run, as startup refuses an outdated schema
+    pub fn write_schema_migration(
+        app: &App,
+        dir: impl AsRef<Path>,
+        description: &str,
+    ) -> Result<Option<PathBuf>, MigrationError> {
+        let dir = dir.as_ref();
+        let db = app.world.resource::<SqlxSqliteDatabaseResource>();
+        std::fs::create_dir_all(dir)?;
+        Migrations::from_dir(dir)?.run(db)?;
+
+        let diff = match app.world.get_resource::<SchemaValidation>() {
+            Some(registered) => registered.diff(db)?,
+            None => return Ok(None),
+        };
+        if diff.is_empty() {
+            return Ok(None);
+        }
+
+        Migrations::write_migration(dir, description, &diff.to_sql()).map(Some)
+    }
+}
+
+// Writes a registered component of a request
+struct ComponentWriter {
+    table: TableSchema,
+    inserts_row: bool,
+    system: Box<dyn System<In = (RequestId, FlushPhase), Out = Result<(), ()>>>,
+}
+
+// Writes the registered components of each flushed request, see App::register_db_component
+// The writers are ordered by the foreign keys between their tables, parents first
+#[derive(Resource, Default)]
+pub struct FlushPipeline {
+    registered: HashSet<TypeId>,
+    writers: Vec<ComponentWriter>,
+    ordered: bool,
+}
+
+impl FlushPipeline {
+    pub fn is_empty(&self) -> bool {
+        self.writers.is_empty()
+    }
+
+    // Orders the writers so referenced tables are written first and, within a table, the
+    // components inserting the row come before those setting its columns
+    pub fn order(&mut self) -> Result<(), ForeignKeyCycle> {
+        if self.ordered {
+            return Ok(());
+        }
+
+        let tables = self
+            .writers
+            .iter()
+            .map(|writer| writer.table.clone())
+            .collect::<Vec<TableSchema>>();
+        let order = tables_in_dependency_order(&tables)?;
+        self.writers.sort_by_key(|writer| {
+            let rank = order.iter().position(|table| table == &writer.table.name);
+            (rank, !writer.inserts_row)
+        });
+        self.ordered = true;
+
+        Ok(())
+    }
+
+    // The tables of the registered components in the order their rows are inserted
+    pub fn tables(&mut self) -> Result<Vec<String>, ForeignKeyCycle> {
+        self.order()?;
+        let mut tables = Vec::new();
+        for writer in &self.writers {
+            if !tables.contains(&writer.table.name) {
+                tables.push(writer.table.name.clone());
+            }
+        }
+        Ok(tables)
+    }
+}
+
+// Orders the flush pipeline at startup so a cycle between the registered tables is found
+// before any request is flushed
+pub fn order_flush_pipeline(mut pipeline: ResMut<FlushPipeline>) {
+    if let Err(cycle) = pipeline.order() {
+        panic!("Failed to order the flush: {}", cycle);
+    }
+}
+
+// Writes every registered component of the request and then commits it, so the request is
+// committed once however many components it changed
+pub fn flush_registered_components(
+    world: &mut World,
+    mut flush_events: Local<ManualEventReader<FlushEvent>>,
+) {
+    let requests = flush_events
+        .read(world.resource::<Events<FlushEvent>>())
+        .map(|flush_event| flush_event.request)
+        .collect::<Vec<RequestId>>();
+
+    world.resource_scope(|world, mut pipeline: Mut<FlushPipeline>| {
+        // Apps flushing with their own flush_component_to_db systems commit the requests themselves
+        if pipeline.is_empty() {
+            return;
+        }
+        // Components registered after startup
+        if let Err(cycle) = pipeline.order() {
+            panic!("Failed to order the flush: {}", cycle);
+        }
+
+        for request in requests {
+            for writer in pipeline.writers.iter_mut() {
+                writer
+                    .system
+                    .run((request, FlushPhase::Write), world)
+                    .unwrap();
+            }
+            for writer in pipeline.writers.iter_mut().rev() {
+                writer
+                    .system
+                    .run((request, FlushPhase::Delete), world)
+                    .unwrap();
+            }
+            world
+                .resource::<SqlxSqliteDatabaseResource>()
+                .commit_transaction(request);
+            if let Some(audit_log) = world.get_resource::<AuditLog>() {
+                audit_log.finish_request(request);
+            }
+        }
+    });
+}
+
+pub trait DatabaseComponentAppExt {
+    // Persists the component: it is written when its request is flushed and its table is added
+    // to the SchemaValidation resource, so it is checked against the database at startup
+    // Its removals are recorded so they are written however many updates later the request is flushed
+    fn register_db_component<C>(&mut self) -> &mut Self
+    where
+        C: ComponentMapper<
+                Component = C,
+                Executor = <SqlxSqliteDatabaseResource as DatabaseResource>::Transaction,
+            > + Component;
+}
+
+impl DatabaseComponentAppExt for App {
+    fn register_db_component<C>(&mut self) -> &mut Self
+    where
+        C: ComponentMapper<
+                Component = C,
+                Executor = <SqlxSqliteDatabaseResource as DatabaseResource>::Transaction,
+            > + Component,
+    {
+        let world = &mut self.world;
+        if world
+            .get_resource_or_insert_with(FlushPipeline::default)
+            .registered
+            .contains(&TypeId::of::<C>())
+        {
+            return self;
+        }
+
+        let mut system = Box::new(IntoSystem::into_system(
+            write_component_to_db::<C, SqlxSqliteDatabaseResource>,
+        ));
+        system.initialize(world);
+
+        let mut pipeline = world.resource_mut::<FlushPipeline>();
+        pipeline.registered.insert(TypeId::of::<C>());
+        pipeline.writers.push(ComponentWriter {
+            table: C::table_schema(),
+            inserts_row: C::INSERTS_ROW,
+            system,
+        });
+        pipeline.ordered = false;
+
+        world
+            .get_resource_or_insert_with(SchemaValidation::default)
+            .register::<C>();
+
+        world.get_resource_or_insert_with(RemovedDatabaseComponents::default);
+        self.add_systems(
+            PostUpdate,
+            record_removed_components::<C>.before(flush_registered_components),
+        )
     }
 }
@@ -0,0 +1,148 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+}
+
+fn setup_app(audited: bool) -> App {
+    common::setup_app(
+        |app| {
+            app.register_db_component::<User>();
+            if audited {
+                app.init_resource::<AuditLog>();
+            }
+        },
+        "INSERT INTO users (id, name) VALUES (1, 'Bob'), (2, 'Alice')",
+    )
+}
+
+fn edit_users(app: &mut App, request: RequestId) {
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&mut User>| {
+            block_on(users.get_mut(&(1, request))).unwrap().name = "Robert".to_string();
+            block_on(users.delete(&(2, request))).unwrap();
+            let user = User {
+                name: "Carol".to_string(),
+            };
+            block_on(users.create(user, request)).unwrap();
+        });
+    flush(app, request);
+}
+
+type AuditRow = (
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    String,
+    Option<String>,
+);
+
+fn audit_rows(app: &App) -> Vec<AuditRow> {
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(
+        sqlx::query_as(
+            "SELECT table_name, operation, before, after, changed_at, actor \
+             FROM audit_log ORDER BY operation",
+        )
+        .fetch_all(&pool),
+    )
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flushing_records_each_write_with_its_actor() {
+    let mut app = setup_app(true);
+    let request = start_request(&app);
+    app.world.resource::<AuditLog>().set_actor(request, "admin");
+
+    edit_users(&mut app, request);
+
+    let row = |operation: &str, before: Option<&str>, after: Option<&str>| {
+        (
+            "users".to_string(),
+            operation.to_string(),
+            before.map(str::to_string),
+            after.map(str::to_string),
+            NOW_TEXT.to_string(),
+            Some("admin".to_string()),
+        )
+    };
+    let rows = audit_rows(&app);
+    assert_eq!(rows.len(), 3);
+    assert_eq!(
+        rows[0],
+        row("delete", Some(r#"{"id":2,"name":"Alice"}"#), None)
+    );
+    assert_eq!(
+        rows[2],
+        row(
+            "update",
+            Some(r#"{"id":1,"name":"Bob"}"#),
+            Some(r#"{"id":1,"name":"Robert"}"#)
+        )
+    );
+
+    // The created user is given its key by the flush so only its name is compared
+    let (table_name, operation, before, after, _, actor) = rows[1].clone();
+    assert_eq!(
+        (table_name, operation, before, actor),
+        (
+            "users".to_string(),
+            "insert".to_string(),
+            None,
+            Some("admin".to_string())
+        )
+    );
+    assert!(after.unwrap().contains(r#""name":"Carol""#));
+
+    assert_eq!(app.world.resource::<AuditLog>().actor(request), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removing_a_component_that_keeps_its_row_is_not_recorded() {
+    let mut app = setup_app(true);
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            block_on(users.get(&(1, request))).unwrap();
+        });
+    let bob = app
+        .world
+        .query_filtered::<Entity, With<User>>()
+        .single(&app.world);
+    app.world.entity_mut(bob).remove::<User>();
+    flush(&mut app, request);
+
+    assert!(audit_rows(&app).is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_are_not_recorded_without_an_audit_log() {
+    let mut app = setup_app(false);
+    let request = start_request(&app);
+
+    edit_users(&mut app, request);
+
+    // Not even the audit table is created
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let tables: i64 = block_on(
+        sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE name = 'audit_log'")
+            .fetch_one(&pool),
+    )
+    .unwrap();
+    assert_eq!(tables, 0);
+}
@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "readings"]
+pub struct Reading {
+    pub sensor: String,
+    pub value: i64,
+    pub unit: String,
+    pub valid: bool,
+}
+
+fn setup_app() -> App {
+    common::setup_app(
+        |app| {
+            app.register_db_component::<Reading>();
+        },
+        "",
+    )
+}
+
+fn readings(app: &App) -> Vec<(String, i64)> {
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query_as("SELECT sensor, value FROM readings ORDER BY value").fetch_all(&pool))
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn inserts_more_rows_than_fit_in_one_statement() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    // Five parameters a row, so these need more than SQLite's limit
+    let created = SQLITE_MAX_BIND_PARAMETERS / 5 + 100;
+    app.world
+        .run_system_once(move |readings: DatabaseQuery<&Reading>| {
+            block_on(async {
+                for value in 0..created as i64 {
+                    readings
+                        .create(
+                            Reading {
+                                sensor: format!("sensor-{}", value % 3),
+                                value,
+                                unit: "C".to_string(),
+                                valid: true,
+                            },
+                            request,
+                        )
+                        .await
+                        .unwrap();
+                }
+            })
+        });
+
+    flush(&mut app, request);
+
+    let rows = readings(&app);
+    assert_eq!(rows.len(), created);
+    assert_eq!(rows[4], ("sensor-1".to_string(), 4));
+    assert_eq!(rows[created - 1].1, created as i64 - 1);
+}
+
+// SQLite's changes() counts the rows of the last statement only, so a final chunk of 100 rows
+// shows the rows went in a statement per chunk rather than one per row
+#[tokio::test(flavor = "multi_thread")]
+async fn each_chunk_of_rows_is_inserted_by_one_statement() {
+    let app = setup_app();
+    let request = start_request(&app);
+    let db = app.world.resource::<SqlxSqliteDatabaseResource>();
+
+    let chunk = SQLITE_MAX_BIND_PARAMETERS / 5;
+    let ids = (1..=chunk as i64 + 100)
+        .map(DatabaseEntityId::Integer)
+        .collect::<Vec<_>>();
+    let reading = Reading {
+        sensor: "a".to_string(),
+        value: 1,
+        unit: "C".to_string(),
+        valid: true,
+    };
+    let components = ids.iter().map(|id| (id, &reading)).collect::<Vec<_>>();
+
+    let mut tr = db.get_transaction(request);
+    block_on(Reading::insert_components(&mut tr, &components)).unwrap();
+
+    let mut guard = block_on(tr.lock());
+    let tr = guard.a.as_mut().unwrap();
+    let (last_statement, total): (i64, i64) = block_on(
+        sqlx::query_as("SELECT changes(), (SELECT COUNT(*) FROM readings)").fetch_one(&mut **tr),
+    )
+    .unwrap();
+    assert_eq!((last_statement, total), (100, chunk as i64 + 100));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updates_and_inserts_of_a_request_are_written_together() {
+    let mut app = setup_app();
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(
+        sqlx::query(
+            "INSERT INTO readings (id, sensor, value, unit, valid) VALUES \
+             (1, 'a', 1, 'C', 1), (2, 'b', 2, 'C', 1), (3, 'c', 3, 'C', 1)",
+        )
+        .execute(&pool),
+    )
+    .unwrap();
+
+    let request = start_request(&app);
+    app.world
+        .run_system_once(move |readings: DatabaseQuery<&mut Reading>| {
+            block_on(async {
+                for id in [1, 3] {
+                    readings.get_mut(&(id, request)).await.unwrap().value *= 10;
+                }
+                readings
+                    .create(
+                        Reading {
+                            sensor: "d".to_string(),
+                            value: 4,
+                            unit: "C".to_string(),
+                            valid: true,
+                        },
+                        request,
+                    )
+                    .await
+                    .unwrap();
+            })
+        });
+
+    flush(&mut app, request);
+
+    assert_eq!(
+        readings(&app),
+        vec![
+            ("b".to_string(), 2),
+            ("d".to_string(), 4),
+            ("a".to_string(), 10),
+            ("c".to_string(), 30)
+        ]
+    );
+}
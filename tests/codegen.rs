@@ -0,0 +1,124 @@
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::codegen::generate_components;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+// The components generated for the schema below, compiled to check the generated code is valid
+#[path = "codegen/components.rs"]
+#[allow(dead_code)]
+mod components;
+
+use components::*;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, nickname TEXT, buyer BOOLEAN, is_admin BOOLEAN NOT NULL DEFAULT 0)",
+    "CREATE TABLE purchased_items (item INTEGER, buyer INTEGER, quantity INTEGER NOT NULL, PRIMARY KEY (item, buyer))",
+    "CREATE TABLE countries (code TEXT PRIMARY KEY, name TEXT NOT NULL, population INTEGER)",
+    "CREATE TABLE accounts (user_id INTEGER PRIMARY KEY, balance REAL NOT NULL, type TEXT NOT NULL)",
+    "CREATE TABLE logs (message TEXT NOT NULL)",
+    "CREATE TABLE settings (id INTEGER PRIMARY KEY, async INTEGER NOT NULL, try TEXT, año INTEGER, self BOOLEAN)",
+];
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(async {
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        sqlx::query("INSERT INTO users (id, name, buyer, is_admin) VALUES (1, 'Bob', 1, 1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO accounts (user_id, balance, type) VALUES (1, 2.5, 'savings')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO settings (id, async, try, año, self) VALUES (1, 3, 'x', 7, 1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+    });
+
+    world
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn components_are_generated_for_every_table() {
+    let world = setup_world();
+
+    let source = generate_components(world.resource::<SqlxSqliteDatabaseResource>()).unwrap();
+
+    assert_eq!(source, include_str!("codegen/components.rs"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn generated_components_read_the_database() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(
+        move |users: DatabaseQuery<(&User, &Buyer, &IsAdmin)>,
+              accounts: DatabaseQuery<&Account>| {
+            block_on(async {
+                let (user, _, _) = users.get(&(1, request)).await.unwrap();
+                assert_eq!(user.name, "Bob");
+                assert_eq!(user.nickname, None);
+
+                let account = accounts.get(&(1, request)).await.unwrap();
+                assert_eq!(account.balance, 2.5);
+                assert_eq!(account.type_, "savings");
+            })
+        },
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn generated_names_that_are_not_identifiers_read_their_columns() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let setting = world.run_system_once(move |settings: DatabaseQuery<(&Setting, &MarkerSelf)>| {
+        let (setting, _) = block_on(settings.get(&(1, request))).unwrap();
+        (setting.async_, setting.try_.clone(), setting.a_o)
+    });
+
+    assert_eq!(setting, (3, Some("x".to_string()), Some(7)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn generated_text_keyed_components_are_created_with_their_key() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |countries: DatabaseQuery<&Country>| {
+        let country = Country {
+            code: "NZ".to_string(),
+            name: "New Zealand".to_string(),
+            population: None,
+        };
+        block_on(countries.create(country, request)).unwrap();
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Country>, SqlxSqliteDatabaseResource>);
+
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let countries = block_on(
+        sqlx::query_as::<_, (String, String)>("SELECT code, name FROM countries").fetch_all(&pool),
+    )
+    .unwrap();
+    assert_eq!(
+        countries,
+        vec![("NZ".to_string(), "New Zealand".to_string())]
+    );
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "accounts"]
+#[primary_key = "user_id"]
+pub struct Account {
+    pub balance: f64,
+    #[column = "type"]
+    pub type_: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "countries"]
+#[key_type = "text"]
+pub struct Country {
+    #[main_key]
+    pub code: String,
+    pub name: String,
+    pub population: Option<i64>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "logs"]
+// TODO: the table has no primary key so the rowid is used
+#[primary_key = "rowid"]
+pub struct Log {
+    pub message: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "purchased_items"]
+pub struct PurchasedItem {
+    #[main_key]
+    pub item: i64,
+    #[main_key]
+    pub buyer: i64,
+    pub quantity: i64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "settings"]
+pub struct Setting {
+    #[column = "async"]
+    pub async_: i64,
+    #[column = "try"]
+    pub try_: Option<String>,
+    #[column = "año"]
+    pub a_o: Option<i64>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "settings"]
+#[column = "self"]
+pub struct MarkerSelf {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+    pub nickname: Option<String>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+#[column = "is_admin"]
+pub struct IsAdmin {}
@@ -0,0 +1,42 @@
+// Helpers shared by the tests of apps flushing their registered components
+use bevy_app::prelude::*;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+// 2023-11-14 22:13:20 UTC
+pub const NOW: u64 = 1_700_000_000;
+// NOW as SQLite formats it
+pub const NOW_TEXT: &str = "2023-11-14 22:13:20";
+
+// An app with the clock fixed at NOW and the tables of the components registered by `register`,
+// seeded with the given statements, that has run its startup systems
+pub fn setup_app(register: impl FnOnce(&mut App), seed: &str) -> App {
+    let mut app = App::new();
+    app.insert_resource(Clock::fixed_at_secs(NOW))
+        .add_plugins(EntityRelationMapperPlugin);
+    register(&mut app);
+
+    let db = app.world.resource::<SqlxSqliteDatabaseResource>();
+    app.world
+        .resource::<SchemaValidation>()
+        .create_tables(db)
+        .unwrap();
+    if !seed.is_empty() {
+        block_on(sqlx::query(seed).execute(&db.pool())).unwrap();
+    }
+
+    app.update();
+    app
+}
+
+pub fn start_request(app: &App) -> RequestId {
+    app.world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction()
+}
+
+// Flushes the request in the next update
+pub fn flush(app: &mut App, request: RequestId) {
+    app.world.send_event(FlushEvent { request });
+    app.update();
+}
@@ -0,0 +1,6 @@
+// Invalid uses of DBQueryDerive are reported as compile errors pointing at the offending attribute
+#[test]
+fn derive_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,197 @@
+use async_trait::async_trait;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, DBEnum)]
+pub enum OrderStatus {
+    #[default]
+    Pending,
+    Shipped,
+    #[rename = "cancelled"]
+    Cancelled,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, DBEnum)]
+#[enum_type = "integer"]
+pub enum Priority {
+    #[default]
+    Low = 1,
+    High = 10,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "orders"]
+pub struct Order {
+    pub status: OrderStatus,
+    pub priority: Priority,
+    pub note: Option<OrderStatus>,
+}
+
+// Which side of the marketplace a user is on, in place of separate buyer and seller booleans
+#[derive(Component, Debug, Clone, Copy, PartialEq, DBQueryDerive)]
+#[table_name = "users"]
+pub enum Role {
+    Buyer,
+    Seller,
+}
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        sqlx::query(&Order::table_schema().create_table_sql())
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO orders (id, status, priority) VALUES (1, 'Shipped', 10)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, role TEXT)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, name, role) VALUES (1, 'Bob', 'Buyer'), (2, 'Alice', 'Seller'), (3, 'Carol', NULL)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+    });
+    db.commit_transaction(request);
+
+    world
+}
+
+fn fetch_all<T>(world: &World, sql: &str) -> Vec<T>
+where
+    T: for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> + Send + Unpin,
+{
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query_as::<_, T>(sql).fetch_all(&pool)).unwrap()
+}
+
+#[test]
+fn enums_are_stored_as_text_or_integer_columns() {
+    let schema = Order::table_schema();
+    assert_eq!(schema.column("status").unwrap().sql_type, "TEXT");
+    assert_eq!(schema.column("priority").unwrap().sql_type, "INTEGER");
+    assert!(schema.column("note").unwrap().nullable);
+
+    let schema = Role::table_schema();
+    assert_eq!(schema.column("role").unwrap().sql_type, "TEXT");
+    assert!(schema.column("role").unwrap().nullable);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn enum_fields_are_read_and_written() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |orders: DatabaseQuery<&mut Order>| {
+        block_on(async {
+            let mut order = orders.get_mut(&(1, request)).await.unwrap();
+            assert_eq!(order.status, OrderStatus::Shipped);
+            assert_eq!(order.priority, Priority::High);
+            assert_eq!(order.note, None);
+
+            order.status = OrderStatus::Cancelled;
+            order.note = Some(OrderStatus::Pending);
+            orders.create(Order::default(), request).await.unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Order>, SqlxSqliteDatabaseResource>);
+
+    assert_eq!(
+        fetch_all::<(i64, String, i64, Option<String>)>(
+            &world,
+            "SELECT id, status, priority, note FROM orders ORDER BY id"
+        ),
+        vec![
+            (-1, "Pending".to_string(), 1, None),
+            (1, "cancelled".to_string(), 10, Some("Pending".to_string())),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn enum_components_are_stored_in_a_discriminator_column() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let roles = world.run_system_once(move |roles: DatabaseQuery<&Role>| {
+        block_on(
+            roles
+                .load_components::<(&DatabaseEntity, &Role), _>(request, RoleQueryLoadAll(request)),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(db_entity, role)| (db_entity.id.clone(), *role))
+        .collect::<Vec<_>>()
+    });
+    assert_eq!(
+        roles,
+        vec![(1.into(), Role::Buyer), (2.into(), Role::Seller)]
+    );
+
+    world.run_system_once(move |roles: DatabaseQuery<&mut Role>| {
+        block_on(async {
+            let mut role = roles.get_mut(&(1, request)).await.unwrap();
+            *role = Role::Seller;
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Role>, SqlxSqliteDatabaseResource>);
+
+    assert_eq!(
+        fetch_all::<(i64, Option<String>)>(&world, "SELECT id, role FROM users ORDER BY id"),
+        vec![
+            (1, Some("Seller".to_string())),
+            (2, Some("Seller".to_string())),
+            (3, None)
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upserting_an_enum_component_replaces_the_stored_variant() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |roles: DatabaseQuery<&Role>| {
+        block_on(async {
+            roles.upsert(Role::Seller, 1, request).await.unwrap();
+            roles.upsert(Role::Buyer, 3, request).await.unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Role>, SqlxSqliteDatabaseResource>);
+
+    assert_eq!(
+        fetch_all::<(i64, Option<String>)>(&world, "SELECT id, role FROM users ORDER BY id"),
+        vec![
+            (1, Some("Seller".to_string())),
+            (2, Some("Seller".to_string())),
+            (3, Some("Buyer".to_string()))
+        ]
+    );
+}
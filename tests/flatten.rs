@@ -0,0 +1,176 @@
+use async_trait::async_trait;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+#[derive(Debug, Default, Clone, PartialEq, DBEmbedded)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, DBEmbedded)]
+pub struct Address {
+    pub street: String,
+    pub city: String,
+    #[column = "postcode"]
+    pub post_code: Option<String>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "invoices"]
+pub struct Invoice {
+    pub number: String,
+    #[flatten]
+    pub total: Money,
+    #[flatten(prefix = "billing_")]
+    pub billing: Address,
+    #[flatten(prefix = "shipping_")]
+    pub shipping: Address,
+}
+
+fn address(street: &str, city: &str) -> Address {
+    Address {
+        street: street.to_string(),
+        city: city.to_string(),
+        post_code: None,
+    }
+}
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        sqlx::query(&Invoice::table_schema().create_table_sql())
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO invoices VALUES
+                (1, 'INV-1', 100, 'NZD', '1 Queen St', 'Auckland', '1010', '2 King St', 'Wellington', NULL)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+    });
+    db.commit_transaction(request);
+
+    world
+}
+
+#[test]
+fn flattened_fields_are_prefixed_columns_of_the_table() {
+    let columns = Invoice::table_schema()
+        .columns
+        .into_iter()
+        .map(|column| (column.name, column.nullable))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        columns,
+        vec![
+            ("id".to_string(), false),
+            ("number".to_string(), false),
+            ("amount".to_string(), false),
+            ("currency".to_string(), false),
+            ("billing_street".to_string(), false),
+            ("billing_city".to_string(), false),
+            ("billing_postcode".to_string(), true),
+            ("shipping_street".to_string(), false),
+            ("shipping_city".to_string(), false),
+            ("shipping_postcode".to_string(), true),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flattened_fields_are_loaded_updated_and_inserted() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |invoices: DatabaseQuery<&mut Invoice>| {
+        block_on(async {
+            let mut invoice = invoices.get_mut(&(1, request)).await.unwrap();
+            assert_eq!(
+                invoice.total,
+                Money {
+                    amount: 100,
+                    currency: "NZD".to_string(),
+                }
+            );
+            assert_eq!(invoice.billing.post_code, Some("1010".to_string()));
+            assert_eq!(invoice.shipping, address("2 King St", "Wellington"));
+
+            invoice.total.amount = 120;
+            invoice.shipping.post_code = Some("6011".to_string());
+
+            invoices
+                .create(
+                    Invoice {
+                        number: "INV-2".to_string(),
+                        total: Money {
+                            amount: 5,
+                            currency: "AUD".to_string(),
+                        },
+                        billing: address("3 George St", "Sydney"),
+                        shipping: address("4 Collins St", "Melbourne"),
+                    },
+                    request,
+                )
+                .await
+                .unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Invoice>, SqlxSqliteDatabaseResource>);
+
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let rows = block_on(
+        sqlx::query_as::<_, (i64, i64, String, String, Option<String>)>(
+            "SELECT id, amount, currency, shipping_city, shipping_postcode FROM invoices ORDER BY id",
+        )
+        .fetch_all(&pool),
+    )
+    .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (-1, 5, "AUD".to_string(), "Melbourne".to_string(), None),
+            (
+                1,
+                120,
+                "NZD".to_string(),
+                "Wellington".to_string(),
+                Some("6011".to_string())
+            ),
+        ]
+    );
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let sydney = world.run_system_once(move |invoices: DatabaseQuery<&Invoice>| {
+        block_on(invoices.load_components::<(&DatabaseEntity, &Invoice), _>(
+            request,
+            InvoiceQueryLoadWhere(request, Filter::column("billing_city").eq("Sydney")),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|(_, invoice)| invoice.number.clone())
+        .collect::<Vec<_>>()
+    });
+    assert_eq!(sydney, vec!["INV-2".to_string()]);
+}
@@ -0,0 +1,263 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "items"]
+pub struct MarketItem {
+    #[references = "users"]
+    pub seller_id: DatabaseEntityId,
+    pub name: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "purchased_items"]
+pub struct PurchasedItem {
+    #[main_key]
+    #[references = "items"]
+    pub item: DatabaseEntityId,
+    #[main_key]
+    #[references = "users"]
+    pub buyer: DatabaseEntityId,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "teams"]
+pub struct Team {
+    #[references = "players"]
+    pub captain: DatabaseEntityId,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "players"]
+pub struct Player {
+    #[references = "teams"]
+    pub team: DatabaseEntityId,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "categories"]
+pub struct Category {
+    #[references = "categories"]
+    pub parent: Option<DatabaseEntityId>,
+    pub name: String,
+}
+
+fn setup_app() -> App {
+    // Registered children first, the pipeline orders them by their foreign keys
+    common::setup_app(
+        |app| {
+            app.register_db_component::<PurchasedItem>()
+                .register_db_component::<MarketItem>()
+                .register_db_component::<Buyer>()
+                .register_db_component::<User>();
+        },
+        "",
+    )
+}
+
+fn key_of<C: Component>(app: &mut App) -> DatabaseEntityId {
+    let mut entities = app.world.query_filtered::<&DatabaseEntity, With<C>>();
+    entities.single(&app.world).id.clone()
+}
+
+fn count(app: &App, table: &str) -> i64 {
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(&pool))
+        .unwrap()
+}
+
+#[test]
+fn foreign_keys_are_part_of_the_table_schema() {
+    assert_eq!(
+        PurchasedItem::table_schema().create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS purchased_items (item INTEGER NOT NULL, buyer INTEGER NOT NULL, \
+         PRIMARY KEY (item, buyer), FOREIGN KEY (item) REFERENCES items, FOREIGN KEY (buyer) REFERENCES users)"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn references_within_a_table_are_checked_on_commit() {
+    assert_eq!(
+        Category::table_schema().create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS categories (id INTEGER NOT NULL, parent INTEGER, name TEXT NOT NULL, \
+         PRIMARY KEY (id), FOREIGN KEY (parent) REFERENCES categories DEFERRABLE INITIALLY DEFERRED)"
+    );
+
+    let app = setup_app();
+    let db = app.world.resource::<SqlxSqliteDatabaseResource>();
+    EntityRelationMapperPlugin::create_schema::<Category>(db).unwrap();
+
+    // The child is written before its parent, as can happen between the rows of one table
+    let request = db.start_new_transaction();
+    let mut tr = db.get_transaction(request);
+    for (id, parent) in [(2, Some(1.into())), (1, None)] {
+        let category = Category {
+            parent,
+            name: format!("category-{}", id),
+        };
+        block_on(Category::insert_component(&mut tr, &id.into(), &category)).unwrap();
+    }
+    db.commit_transaction(request);
+
+    assert_eq!(count(&app, "categories"), 2);
+}
+
+#[test]
+fn tables_are_ordered_by_their_foreign_keys() {
+    assert_eq!(
+        tables_in_dependency_order(&<(PurchasedItem, Buyer, MarketItem, User)>::table_schemas()),
+        Ok(vec![
+            "users".to_string(),
+            "items".to_string(),
+            "purchased_items".to_string()
+        ])
+    );
+    assert_eq!(
+        tables_in_dependency_order(&<(Player, Team)>::table_schemas()),
+        Err(ForeignKeyCycle {
+            tables: vec![
+                "players".to_string(),
+                "teams".to_string(),
+                "players".to_string()
+            ]
+        })
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn foreign_keys_are_enforced() {
+    let app = setup_app();
+
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let orphan = block_on(
+        sqlx::query("INSERT INTO purchased_items (item, buyer) VALUES (1, 1)").execute(&pool),
+    );
+    assert!(orphan.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tables_are_flushed_parents_first() {
+    let mut app = setup_app();
+
+    assert_eq!(
+        app.world.resource_mut::<FlushPipeline>().tables().unwrap(),
+        vec![
+            "users".to_string(),
+            "items".to_string(),
+            "purchased_items".to_string()
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rows_are_inserted_parents_first_and_deleted_children_first() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            block_on(users.create(
+                User {
+                    name: "Alice".to_string(),
+                },
+                request,
+            ))
+            .unwrap();
+        });
+    let alice = key_of::<User>(&mut app);
+    let alice_entity = app
+        .world
+        .query_filtered::<Entity, With<User>>()
+        .single(&app.world);
+    app.world.entity_mut(alice_entity).insert(Buyer {});
+
+    let seller_id = alice.clone();
+    app.world
+        .run_system_once(move |items: DatabaseQuery<&MarketItem>| {
+            block_on(items.create(
+                MarketItem {
+                    seller_id: seller_id.clone(),
+                    name: "corn".to_string(),
+                },
+                request,
+            ))
+            .unwrap();
+        });
+    let corn = key_of::<MarketItem>(&mut app);
+
+    let purchase = PurchasedItem {
+        item: corn.clone(),
+        buyer: alice.clone(),
+    };
+    app.world
+        .run_system_once(move |purchases: DatabaseQuery<&PurchasedItem>| {
+            block_on(purchases.create(purchase.clone(), request)).unwrap();
+        });
+
+    flush(&mut app, request);
+    assert_eq!(count(&app, "users"), 1);
+    assert_eq!(count(&app, "items"), 1);
+    assert_eq!(count(&app, "purchased_items"), 1);
+
+    // Entities stay with the request that loaded them, so start the next one from the database
+    let loaded = app
+        .world
+        .query_filtered::<Entity, With<DatabaseEntity>>()
+        .iter(&app.world)
+        .collect::<Vec<Entity>>();
+    for entity in loaded {
+        app.world.despawn(entity);
+    }
+
+    let request = start_request(&app);
+    let purchase_key = DatabaseEntityId::Composite(vec![corn.clone(), alice.clone()]);
+    app.world.run_system_once(
+        move |users: DatabaseQuery<&User>,
+              items: DatabaseQuery<&MarketItem>,
+              purchases: DatabaseQuery<&PurchasedItem>| {
+            block_on(async {
+                users.delete(&(alice.clone(), request)).await.unwrap();
+                items.delete(&(corn.clone(), request)).await.unwrap();
+                purchases
+                    .delete(&(purchase_key.clone(), request))
+                    .await
+                    .unwrap();
+            })
+        },
+    );
+
+    flush(&mut app, request);
+    assert_eq!(count(&app, "users"), 0);
+    assert_eq!(count(&app, "items"), 0);
+    assert_eq!(count(&app, "purchased_items"), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[should_panic(expected = "the foreign keys of the tables teams -> players -> teams form a cycle")]
+async fn cycles_between_registered_tables_are_reported_at_startup() {
+    let mut app = App::new();
+    app.add_plugins(EntityRelationMapperPlugin)
+        .register_db_component::<Team>()
+        .register_db_component::<Player>();
+
+    app.update();
+}
@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+use serde::{Deserialize, Serialize};
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Details {
+    pub color: String,
+    pub sizes: Vec<i64>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "products"]
+pub struct Product {
+    pub name: String,
+    #[json]
+    pub details: Details,
+    #[json]
+    pub tags: Vec<String>,
+}
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        sqlx::query(&Product::table_schema().create_table_sql())
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"INSERT INTO products (id, name, details, tags) VALUES
+                (1, 'shirt', '{"color":"red","sizes":[1,2]}', '["summer"]'),
+                (2, 'hat', '{"color":"blue","sizes":[3]}', '[]'),
+                (3, 'scarf', '{"color":"red","sizes":[]}', '["winter"]')"#,
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+    });
+    db.commit_transaction(request);
+
+    world
+}
+
+fn load_where(world: &mut World, filter: Filter) -> Vec<(DatabaseEntityId, String)> {
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |products: DatabaseQuery<&Product>| {
+        block_on(products.load_components::<(&DatabaseEntity, &Product), _>(
+            request,
+            ProductQueryLoadWhere(request, filter.clone()),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|(db_entity, product)| (db_entity.id.clone(), product.name.clone()))
+        .collect()
+    })
+}
+
+#[test]
+fn json_fields_are_stored_as_text() {
+    let schema = Product::table_schema();
+    assert_eq!(schema.column("details").unwrap().sql_type, "TEXT");
+    assert_eq!(schema.column("tags").unwrap().sql_type, "TEXT");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn json_fields_are_serialised_and_deserialised() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |products: DatabaseQuery<&mut Product>| {
+        block_on(async {
+            let mut product = products.get_mut(&(1, request)).await.unwrap();
+            assert_eq!(
+                product.details,
+                Details {
+                    color: "red".to_string(),
+                    sizes: vec![1, 2],
+                }
+            );
+            assert_eq!(product.tags, vec!["summer".to_string()]);
+
+            product.details.sizes.push(3);
+            product.tags.push("sale".to_string());
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Product>, SqlxSqliteDatabaseResource>);
+
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let (details, tags) = block_on(
+        sqlx::query_as::<_, (String, String)>("SELECT details, tags FROM products WHERE id = 1")
+            .fetch_one(&pool),
+    )
+    .unwrap();
+    assert_eq!(details, r#"{"color":"red","sizes":[1,2,3]}"#);
+    assert_eq!(tags, r#"["summer","sale"]"#);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn components_are_filtered_on_json_paths() {
+    let mut world = setup_world();
+
+    assert_eq!(
+        load_where(
+            &mut world,
+            Filter::json_path("details", "$.color").eq("red")
+        ),
+        vec![
+            (1.into(), "shirt".to_string()),
+            (3.into(), "scarf".to_string())
+        ]
+    );
+    assert_eq!(
+        load_where(
+            &mut world,
+            Filter::json_path("details", "$.color")
+                .eq("red")
+                .and(Filter::json_path("tags", "$[0]").eq("winter"))
+        ),
+        vec![(3.into(), "scarf".to_string())]
+    );
+    assert_eq!(
+        load_where(
+            &mut world,
+            Filter::json_path("details", "$.sizes[0]")
+                .ge(3)
+                .or(Filter::column("name").eq("shirt"))
+        ),
+        vec![
+            (1.into(), "shirt".to_string()),
+            (2.into(), "hat".to_string())
+        ]
+    );
+}
@@ -0,0 +1,273 @@
+use async_trait::async_trait;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[marker_table(table = "admins", key = "user_id")]
+pub struct Admin {}
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        sqlx::query(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, buyer BOOLEAN NOT NULL DEFAULT 0)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+        sqlx::query(&Admin::table_schema().create_table_sql())
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO admins (user_id) VALUES (2)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, name, buyer) VALUES (1, 'Bob', 1), (2, 'Alice', 0), (3, 'Carol', 1)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+    });
+    db.commit_transaction(request);
+
+    world
+}
+
+fn buyer_column(world: &World) -> Vec<(i64, bool)> {
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query_as("SELECT id, buyer FROM users ORDER BY id").fetch_all(&pool)).unwrap()
+}
+
+fn admin_rows(world: &World) -> Vec<i64> {
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query_scalar("SELECT user_id FROM admins ORDER BY user_id").fetch_all(&pool))
+        .unwrap()
+}
+
+fn find_loaded_entity(world: &mut World, id: i64) -> Entity {
+    let mut entities = world.query::<(Entity, &DatabaseEntity)>();
+    entities
+        .iter(world)
+        .find(|(_, db_entity)| db_entity.id == DatabaseEntityId::Integer(id))
+        .map(|(entity, _)| entity)
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn load_all_only_loads_rows_with_the_marker() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let buyers = world.run_system_once(move |buyers: DatabaseQuery<&Buyer>| {
+        block_on(buyers.load_components::<&DatabaseEntity, _>(request, BuyerQueryLoadAll(request)))
+            .unwrap()
+            .into_iter()
+            .map(|db_entity| db_entity.id.clone())
+            .collect::<Vec<_>>()
+    });
+
+    assert_eq!(
+        buyers,
+        vec![DatabaseEntityId::Integer(1), DatabaseEntityId::Integer(3)]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn load_where_combines_the_filter_with_the_marker() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let buyers = world.run_system_once(move |buyers: DatabaseQuery<&Buyer>| {
+        block_on(buyers.load_components::<&DatabaseEntity, _>(
+            request,
+            BuyerQueryLoadWhere(request, Filter::column("name").ne("Carol")),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|db_entity| db_entity.id.clone())
+        .collect::<Vec<_>>()
+    });
+
+    assert_eq!(buyers, vec![DatabaseEntityId::Integer(1)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn adding_a_marker_writes_true() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |users: DatabaseQuery<&mut User>| {
+        block_on(users.get_mut(&(2, request))).unwrap();
+    });
+    let alice = find_loaded_entity(&mut world, 2);
+    world.entity_mut(alice).insert(Buyer {});
+
+    world.send_event(FlushEvent { request });
+    world.run_system_once(
+        flush_component_to_db::<(Option<&User>, Option<&Buyer>), SqlxSqliteDatabaseResource>,
+    );
+
+    assert_eq!(buyer_column(&world), vec![(1, true), (2, true), (3, true)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removing_a_marker_writes_false() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |buyers: DatabaseQuery<&Buyer>| {
+        block_on(buyers.get(&(1, request))).unwrap();
+    });
+    let bob = find_loaded_entity(&mut world, 1);
+    world.entity_mut(bob).remove::<Buyer>();
+
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Buyer>, SqlxSqliteDatabaseResource>);
+
+    assert_eq!(
+        buyer_column(&world),
+        vec![(1, false), (2, false), (3, true)]
+    );
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let is_buyer = world.run_system_once(move |buyers: DatabaseQuery<Option<&Buyer>>| {
+        block_on(buyers.get(&(1, request))).unwrap().is_some()
+    });
+    assert!(!is_buyer);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removals_are_only_written_by_the_flush_of_their_request() {
+    let mut world = setup_world();
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    let other_request = db.start_new_transaction();
+
+    world.run_system_once(move |buyers: DatabaseQuery<&Buyer>| {
+        block_on(buyers.get(&(3, request))).unwrap();
+    });
+    let carol = find_loaded_entity(&mut world, 3);
+    world.entity_mut(carol).remove::<Buyer>();
+
+    world.send_event(FlushEvent {
+        request: other_request,
+    });
+    world.run_system_once(flush_component_to_db::<Option<&Buyer>, SqlxSqliteDatabaseResource>);
+    assert_eq!(buyer_column(&world), vec![(1, true), (2, false), (3, true)]);
+
+    world.resource_mut::<Events<FlushEvent>>().clear();
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Buyer>, SqlxSqliteDatabaseResource>);
+    assert_eq!(
+        buyer_column(&world),
+        vec![(1, true), (2, false), (3, false)]
+    );
+}
+
+#[test]
+fn table_markers_are_described_by_their_own_table() {
+    let schema = Admin::table_schema();
+    assert_eq!(schema.name, "admins");
+    assert_eq!(schema.primary_key, vec!["user_id".to_string()]);
+    assert_eq!(
+        schema.create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS admins (user_id INTEGER NOT NULL, PRIMARY KEY (user_id))"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn table_markers_are_present_when_their_row_exists() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let admins = world.run_system_once(move |users: DatabaseQuery<(&User, Option<&Admin>)>| {
+        block_on(async {
+            let mut admins = Vec::new();
+            for id in 1..=3 {
+                let (user, admin) = users.get(&(id, request)).await.unwrap();
+                admins.push((user.name.clone(), admin.is_some()));
+            }
+            admins
+        })
+    });
+    assert_eq!(
+        admins,
+        vec![
+            ("Bob".to_string(), false),
+            ("Alice".to_string(), true),
+            ("Carol".to_string(), false)
+        ]
+    );
+
+    let loaded = world.run_system_once(move |admins: DatabaseQuery<&Admin>| {
+        block_on(admins.load_components::<&DatabaseEntity, _>(request, AdminQueryLoadAll(request)))
+            .unwrap()
+            .into_iter()
+            .map(|db_entity| db_entity.id.clone())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(loaded, vec![DatabaseEntityId::Integer(2)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn adding_and_removing_table_markers_inserts_and_deletes_rows() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |users: DatabaseQuery<&mut User>| {
+        block_on(users.get_mut(&(1, request))).unwrap();
+    });
+    world.run_system_once(move |admins: DatabaseQuery<&Admin>| {
+        block_on(admins.get(&(2, request))).unwrap();
+    });
+    let bob = find_loaded_entity(&mut world, 1);
+    world.entity_mut(bob).insert(Admin {});
+    let alice = find_loaded_entity(&mut world, 2);
+    world.entity_mut(alice).remove::<Admin>();
+
+    world.send_event(FlushEvent { request });
+    world.run_system_once(
+        flush_component_to_db::<(Option<&User>, Option<&Admin>), SqlxSqliteDatabaseResource>,
+    );
+
+    assert_eq!(admin_rows(&world), vec![1]);
+}
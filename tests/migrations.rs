@@ -0,0 +1,135 @@
+use bevy_app::prelude::*;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+fn migrations() -> Migrations {
+    embed_migrations!("tests/migrations").unwrap()
+}
+
+fn applied_versions(db: &SqlxSqliteDatabaseResource) -> Vec<(i64, String)> {
+    block_on(
+        sqlx::query_as::<_, (i64, String)>(&format!(
+            "SELECT version, description FROM {} ORDER BY version",
+            MIGRATIONS_TABLE
+        ))
+        .fetch_all(&db.pool()),
+    )
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn migrations_are_applied_in_order_once() {
+    let db = SqlxSqliteDatabaseResource::default();
+    let migrations = migrations();
+
+    assert_eq!(migrations.latest_version(), 2);
+    assert_eq!(migrations.run(&db).unwrap(), vec![1, 2]);
+    assert_eq!(migrations.run(&db).unwrap(), Vec::<i64>::new());
+    assert_eq!(
+        applied_versions(&db),
+        vec![
+            (1, "create users".to_string()),
+            (2, "add user emails".to_string())
+        ]
+    );
+
+    // Both statements of the second migration were applied
+    block_on(
+        sqlx::query("INSERT INTO users (id, name, email) VALUES (1, 'Bob', 'bob@example.com')")
+            .execute(&db.pool()),
+    )
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn migrations_are_read_from_a_directory() {
+    let from_dir =
+        Migrations::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/migrations")).unwrap();
+
+    assert_eq!(from_dir.migrations(), migrations().migrations());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_migrations_are_applied_to_a_migrated_database() {
+    let db = SqlxSqliteDatabaseResource::default();
+    let first = Migrations::new(migrations().migrations()[..1].to_vec()).unwrap();
+
+    assert_eq!(first.run(&db).unwrap(), vec![1]);
+    assert_eq!(migrations().run(&db).unwrap(), vec![2]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_migrations_are_not_recorded() {
+    let db = SqlxSqliteDatabaseResource::default();
+    let migrations = Migrations::new(vec![
+        Migration::new(
+            1,
+            "create users",
+            "CREATE TABLE users (id INTEGER PRIMARY KEY)",
+        ),
+        Migration::new(
+            2,
+            "broken",
+            "CREATE TABLE items (id INTEGER PRIMARY KEY); NOT SQL",
+        ),
+    ])
+    .unwrap();
+
+    assert!(matches!(migrations.run(&db), Err(MigrationError::Sqlx(_))));
+    assert_eq!(applied_versions(&db), vec![(1, "create users".to_string())]);
+    // The statements of the failed migration were rolled back
+    assert!(block_on(sqlx::query("SELECT * FROM items").execute(&db.pool())).is_err());
+}
+
+#[test]
+fn invalid_migrations_are_rejected() {
+    assert!(matches!(
+        Migration::from_file("create_users.sql", ""),
+        Err(MigrationError::InvalidFileName(_))
+    ));
+    assert!(matches!(
+        Migrations::from_files(vec![("1_a.sql", ""), ("01_b.sql", "")]),
+        Err(MigrationError::DuplicateVersion(1))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn database_ahead_of_the_code_is_refused() {
+    let db = SqlxSqliteDatabaseResource::default();
+    migrations().run(&db).unwrap();
+
+    let first = Migrations::new(migrations().migrations()[..1].to_vec()).unwrap();
+    assert!(matches!(
+        first.run(&db),
+        Err(MigrationError::DatabaseAhead {
+            database_version: 2,
+            code_version: 1
+        })
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[should_panic(expected = "Failed to migrate the database")]
+async fn plugin_refuses_to_start_when_the_database_is_ahead() {
+    let mut app = App::new();
+    app.add_plugins(EntityRelationMapperPlugin);
+    migrations()
+        .run(app.world.resource::<SqlxSqliteDatabaseResource>())
+        .unwrap();
+
+    app.insert_resource(Migrations::new(migrations().migrations()[..1].to_vec()).unwrap());
+    app.update();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plugin_applies_migrations_at_startup() {
+    let mut app = App::new();
+    app.insert_resource(migrations())
+        .add_plugins(EntityRelationMapperPlugin);
+    app.update();
+
+    assert_eq!(
+        applied_versions(app.world.resource::<SqlxSqliteDatabaseResource>()).len(),
+        2
+    );
+}
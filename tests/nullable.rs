@@ -0,0 +1,166 @@
+use async_trait::async_trait;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+    pub nickname: Option<String>,
+    pub age: Option<i64>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        // The buyer column predates the marker so allows NULL
+        sqlx::query(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, nickname TEXT, age INTEGER, buyer BOOLEAN)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, name, nickname, age, buyer) VALUES
+                (1, 'Bob', 'bobby', 30, 1),
+                (2, 'Alice', NULL, NULL, 0),
+                (3, 'Carol', NULL, 25, NULL)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+    });
+    db.commit_transaction(request);
+
+    world
+}
+
+fn load_where(world: &mut World, filter: Filter) -> Vec<String> {
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |users: DatabaseQuery<&User>| {
+        block_on(
+            users.load_components::<&User, _>(request, UserQueryLoadWhere(request, filter.clone())),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|user| user.name.clone())
+        .collect()
+    })
+}
+
+#[test]
+fn option_fields_are_nullable_columns() {
+    let schema = User::table_schema();
+    assert!(!schema.column("name").unwrap().nullable);
+    assert!(schema.column("nickname").unwrap().nullable);
+    assert!(schema.column("age").unwrap().nullable);
+    assert!(schema
+        .create_table_sql()
+        .contains("nickname TEXT, age INTEGER,"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn option_fields_read_and_write_null() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |users: DatabaseQuery<&mut User>| {
+        block_on(async {
+            let mut bob = users.get_mut(&(1, request)).await.unwrap();
+            assert_eq!(bob.nickname, Some("bobby".to_string()));
+            bob.nickname = None;
+
+            let mut alice = users.get_mut(&(2, request)).await.unwrap();
+            assert_eq!(alice.age, None);
+            alice.age = Some(41);
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&User>, SqlxSqliteDatabaseResource>);
+
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let rows = block_on(
+        sqlx::query_as::<_, (i64, Option<String>, Option<i64>)>(
+            "SELECT id, nickname, age FROM users ORDER BY id",
+        )
+        .fetch_all(&pool),
+    )
+    .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (1, None, Some(30)),
+            (2, None, Some(41)),
+            (3, None, Some(25))
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn components_are_filtered_on_null_columns() {
+    let mut world = setup_world();
+
+    assert_eq!(
+        load_where(&mut world, Filter::column("nickname").is_null()),
+        vec!["Alice".to_string(), "Carol".to_string()]
+    );
+    assert_eq!(
+        load_where(
+            &mut world,
+            Filter::column("nickname")
+                .is_null()
+                .and(Filter::column("age").is_not_null())
+        ),
+        vec!["Carol".to_string()]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn null_marker_columns_are_absent_markers() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let buyers = world.run_system_once(move |users: DatabaseQuery<(&User, Option<&Buyer>)>| {
+        block_on(async {
+            let mut buyers = Vec::new();
+            for id in 1..=3 {
+                let (user, buyer) = users.get(&(id, request)).await.unwrap();
+                buyers.push((user.name.clone(), buyer.is_some()));
+            }
+            buyers
+        })
+    });
+
+    assert_eq!(
+        buyers,
+        vec![
+            ("Bob".to_string(), true),
+            ("Alice".to_string(), false),
+            ("Carol".to_string(), false)
+        ]
+    );
+}
@@ -0,0 +1,576 @@
+use async_trait::async_trait;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+use sqlx::Row;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DBQueryInfo, DatabaseTransaction};
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "accounts"]
+#[primary_key = "user_id"]
+pub struct Account {
+    pub balance: i64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "accounts"]
+pub struct AccountOwner {
+    #[main_key]
+    pub user_id: i64,
+    pub balance: i64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "purchased_items"]
+pub struct PurchasedItem {
+    #[main_key]
+    pub item: i64,
+    #[main_key]
+    pub buyer: i64,
+    pub quantity: i64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "countries"]
+#[key_type = "text"]
+pub struct Country {
+    #[main_key]
+    pub code: String,
+    pub name: String,
+}
+
+// Text keyed but without a field holding the key, so new components have no key
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "currencies"]
+#[primary_key = "code"]
+#[key_type = "text"]
+pub struct Currency {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "profiles"]
+pub struct Profile {
+    #[column = "display_name"]
+    pub name: String,
+    #[readonly]
+    pub version: i64,
+    #[skip]
+    pub views: i64,
+}
+
+#[cfg(feature = "uuid")]
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "sessions"]
+#[key_type = "uuid"]
+pub struct Session {
+    pub user: i64,
+}
+
+fn setup_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+    world.init_resource::<Events<FlushEvent>>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, buyer BOOLEAN)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO users (id, name, buyer) VALUES (1, 'Bob', 1), (2, 'Alice', 0)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE accounts (user_id INTEGER PRIMARY KEY, balance INTEGER)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO accounts (user_id, balance) VALUES (1, 50)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE countries (code TEXT PRIMARY KEY, name TEXT)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO countries (code, name) VALUES ('NZ', 'New Zealand')")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE purchased_items (item INTEGER, buyer INTEGER, quantity INTEGER, PRIMARY KEY (item, buyer))",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO purchased_items (item, buyer, quantity) VALUES (3, 1, 1), (4, 1, 1)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+        sqlx::query(
+            "CREATE TABLE profiles (id INTEGER PRIMARY KEY, display_name TEXT, version INTEGER DEFAULT 1)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO profiles (id, display_name, version) VALUES (1, 'bob', 3)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+    });
+    db.commit_transaction(request);
+
+    world
+}
+
+struct UsersAndBuyersQuery(RequestId);
+
+#[async_trait]
+impl CustomDatabaseQuery<SqlxSqliteDatabaseResource, (User, Option<Buyer>)>
+    for UsersAndBuyersQuery
+{
+    async fn query(
+        &self,
+        tr: DatabaseTransaction<SqlxSqliteDatabaseResource>,
+    ) -> Result<Vec<(DatabaseEntity, (User, Option<Buyer>))>, ()> {
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+        let rows = sqlx::query("SELECT id, name, buyer FROM users ORDER BY id")
+            .fetch_all(&mut **tr)
+            .await
+            .map_err(|_| ())?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let entity = DatabaseEntity {
+                    id: row.get("id"),
+                    persisted: true.into(),
+                    dirty: false,
+                    deleted: false,
+                    request: self.0,
+                };
+                let user = User {
+                    name: row.get("name"),
+                };
+                let buyer = row.get::<bool, _>("buyer").then_some(Buyer {});
+                (entity, (user, buyer))
+            })
+            .collect())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tuple_load_components_loads_every_member() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let loaded = world.run_system_once(
+        move |users: DatabaseQuery<(&User, Option<&Buyer>)>| -> Vec<(DatabaseEntityId, String, bool)> {
+            block_on(
+                users.load_components::<(&DatabaseEntity, &User, Option<&Buyer>), _>(
+                    request,
+                    UsersAndBuyersQuery(request),
+                ),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|(db_entity, user, buyer)| (db_entity.id.clone(), user.name.clone(), buyer.is_some()))
+            .collect()
+        },
+    );
+
+    assert_eq!(
+        loaded,
+        vec![
+            (1.into(), "Bob".to_string(), true),
+            (2.into(), "Alice".to_string(), false)
+        ]
+    );
+}
+
+fn assert_db_query<Q: DBQueryInfo<SqlxSqliteDatabaseResource>>() {}
+
+#[test]
+fn tuples_compose_up_to_twelve_queries() {
+    assert_db_query::<(
+        &User,
+        Option<&Buyer>,
+        Option<&User>,
+        Option<&Buyer>,
+        Option<&User>,
+        Option<&Buyer>,
+        Option<&User>,
+        Option<&Buyer>,
+        Option<&User>,
+        Option<&Buyer>,
+        Option<&User>,
+        Option<&Buyer>,
+    )>();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn custom_primary_key_column_is_used_for_queries() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |accounts: DatabaseQuery<&mut Account>| {
+        block_on(async {
+            let mut account = accounts.get_mut(&(1, request)).await.unwrap();
+            account.balance += 25;
+            accounts
+                .create(Account { balance: 10 }, request)
+                .await
+                .unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Account>, SqlxSqliteDatabaseResource>);
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let owners = world.run_system_once(move |owners: DatabaseQuery<&AccountOwner>| {
+        block_on(
+            owners.load_components::<(&DatabaseEntity, &AccountOwner), _>(
+                request,
+                AccountOwnerQueryLoadAll(request),
+            ),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(db_entity, owner)| (db_entity.id.clone(), owner.user_id, owner.balance))
+        .collect::<Vec<_>>()
+    });
+
+    assert_eq!(owners, vec![((-1).into(), -1, 10), (1.into(), 1, 75)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entities_are_read_from_the_key_columns_of_the_component() {
+    let world = setup_world();
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+
+    let rows = block_on(
+        sqlx::query_as::<_, DataseBaseEntityAndComponent<Account>>(
+            "SELECT user_id, balance FROM accounts",
+        )
+        .fetch_all(&pool),
+    )
+    .unwrap();
+
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].entity.id, 1.into());
+    assert_eq!(rows[0].component.balance, 50);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn composite_keys_load_update_insert_and_delete() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |purchased: DatabaseQuery<&mut PurchasedItem>| {
+        block_on(async {
+            let mut purchased_item = purchased.get_mut(&((3, 1), request)).await.unwrap();
+            purchased_item.quantity += 1;
+
+            purchased.delete(&((4, 1), request)).await.unwrap();
+
+            purchased
+                .create(
+                    PurchasedItem {
+                        item: 3,
+                        buyer: 2,
+                        quantity: 5,
+                    },
+                    request,
+                )
+                .await
+                .unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(
+        flush_component_to_db::<Option<&PurchasedItem>, SqlxSqliteDatabaseResource>,
+    );
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let purchased_items = world.run_system_once(move |purchased: DatabaseQuery<&PurchasedItem>| {
+        block_on(
+            purchased.load_components::<(&DatabaseEntity, &PurchasedItem), _>(
+                request,
+                PurchasedItemQueryLoadAll(request),
+            ),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(db_entity, purchased_item)| (db_entity.id.clone(), purchased_item.quantity))
+        .collect::<Vec<_>>()
+    });
+
+    assert_eq!(
+        purchased_items,
+        vec![((3, 1).into(), 2), ((3, 2).into(), 5)]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn load_all_fails_on_a_row_that_can_not_be_decoded() {
+    let mut world = setup_world();
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(
+        sqlx::query("INSERT INTO accounts (user_id, balance) VALUES (2, 'lots')").execute(&pool),
+    )
+    .unwrap();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let loaded = world.run_system_once(move |accounts: DatabaseQuery<&Account>| {
+        block_on(accounts.load_components::<&Account, _>(request, AccountQueryLoadAll(request)))
+            .map(|accounts| accounts.len())
+    });
+
+    assert_eq!(loaded, Err(()));
+    assert_eq!(world.query::<&Account>().iter(&world).count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn optional_get_is_none_only_when_the_component_is_missing() {
+    let mut world = setup_world();
+    let pool = world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(
+        sqlx::query("INSERT INTO accounts (user_id, balance) VALUES (2, 'lots')").execute(&pool),
+    )
+    .unwrap();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let balances = world.run_system_once(move |accounts: DatabaseQuery<Option<&Account>>| {
+        block_on(async {
+            let mut balances = vec![];
+            for id in [1, 2, 3] {
+                balances.push(
+                    accounts
+                        .get(&(id, request))
+                        .await
+                        .map(|account| account.map(|account| account.balance)),
+                );
+            }
+            balances
+        })
+    });
+
+    // The row that can not be decoded is an error rather than a missing account
+    assert_eq!(balances, vec![Ok(Some(50)), Err(()), Ok(None)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn text_keys_are_loaded_and_taken_from_the_component() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |countries: DatabaseQuery<&mut Country>| {
+        block_on(async {
+            let mut country = countries.get_mut(&("NZ", request)).await.unwrap();
+            assert_eq!(country.code, "NZ");
+            country.name = "Aotearoa New Zealand".to_string();
+            countries
+                .create(
+                    Country {
+                        code: "AU".to_string(),
+                        name: "Australia".to_string(),
+                    },
+                    request,
+                )
+                .await
+                .unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Country>, SqlxSqliteDatabaseResource>);
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let countries = world.run_system_once(move |countries: DatabaseQuery<&Country>| {
+        block_on(countries.load_components::<(&DatabaseEntity, &Country), _>(
+            request,
+            CountryQueryLoadAll(request),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|(db_entity, country)| (db_entity.id.clone(), country.name.clone()))
+        .collect::<Vec<_>>()
+    });
+
+    assert_eq!(
+        countries,
+        vec![
+            ("NZ".into(), "Aotearoa New Zealand".to_string()),
+            ("AU".into(), "Australia".to_string())
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn text_keys_are_not_generated() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    let created = world.run_system_once(move |currencies: DatabaseQuery<&Currency>| {
+        block_on(currencies.create(
+            Currency {
+                name: "Euro".to_string(),
+            },
+            request,
+        ))
+    });
+    assert_eq!(created, Err(()));
+    assert_eq!(world.query::<&Currency>().iter(&world).count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fields_are_mapped_to_columns() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+
+    world.run_system_once(move |profiles: DatabaseQuery<&mut Profile>| {
+        block_on(async {
+            let mut profile = profiles.get_mut(&(1, request)).await.unwrap();
+            assert_eq!(profile.name, "bob");
+            assert_eq!(profile.version, 3);
+            assert_eq!(profile.views, 0);
+
+            profile.name = "bobby".to_string();
+            profile.version = 10;
+            profile.views = 5;
+            profiles
+                .create(
+                    Profile {
+                        name: "alice".to_string(),
+                        version: 10,
+                        views: 5,
+                    },
+                    request,
+                )
+                .await
+                .unwrap();
+        })
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Profile>, SqlxSqliteDatabaseResource>);
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    let profiles = block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+        sqlx::query_as::<_, (i64, String, i64)>(
+            "SELECT id, display_name, version FROM profiles ORDER BY id",
+        )
+        .fetch_all(&mut **tr)
+        .await
+        .unwrap()
+    });
+
+    // The read only version keeps the value from the database and the skipped views are never stored
+    assert_eq!(
+        profiles,
+        vec![(-1, "alice".to_string(), 1), (1, "bobby".to_string(), 3)]
+    );
+
+    // The FromRow impl generated by the derive reads the mapped columns
+    let profile = block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+        sqlx::query_as::<_, Profile>("SELECT * FROM profiles WHERE id = 1")
+            .fetch_one(&mut **tr)
+            .await
+            .unwrap()
+    });
+    assert_eq!(profile.name, "bobby");
+    assert_eq!(profile.version, 3);
+    assert_eq!(profile.views, 0);
+}
+
+#[cfg(feature = "uuid")]
+#[tokio::test(flavor = "multi_thread")]
+async fn uuid_keys_are_generated_and_loaded() {
+    let mut world = setup_world();
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    block_on(async {
+        let db = world.resource::<SqlxSqliteDatabaseResource>();
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+        sqlx::query("CREATE TABLE sessions (id BLOB PRIMARY KEY, user INTEGER)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+    });
+
+    world.run_system_once(move |sessions: DatabaseQuery<&Session>| {
+        block_on(sessions.create(Session { user: 1 }, request)).unwrap()
+    });
+    world.send_event(FlushEvent { request });
+    world.run_system_once(flush_component_to_db::<Option<&Session>, SqlxSqliteDatabaseResource>);
+
+    let request = world
+        .resource::<SqlxSqliteDatabaseResource>()
+        .start_new_transaction();
+    let sessions = world.run_system_once(move |sessions: DatabaseQuery<&Session>| {
+        block_on(sessions.load_components::<(&DatabaseEntity, &Session), _>(
+            request,
+            SessionQueryLoadAll(request),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|(db_entity, session)| (db_entity.id.clone(), session.user))
+        .collect::<Vec<_>>()
+    });
+
+    assert_eq!(sessions.len(), 1);
+    assert!(matches!(sessions[0], (DatabaseEntityId::Uuid(_), 1)));
+}
@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Seller {}
+
+fn setup_app() -> App {
+    common::setup_app(
+        |app| {
+            app.register_db_component::<User>()
+                .register_db_component::<Buyer>()
+                // Registering again does not write the component twice
+                .register_db_component::<User>();
+        },
+        "INSERT INTO users (id, name) VALUES (1, 'Bob')",
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registered_components_are_validated() {
+    let app = setup_app();
+
+    let tables = app.world.resource::<SchemaValidation>().tables();
+    assert_eq!(tables.len(), 1);
+    assert!(tables[0].column("name").is_some());
+    assert!(tables[0].column("buyer").is_some());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[should_panic(expected = "column users.seller does not exist")]
+async fn plugin_refuses_to_start_without_the_tables_of_registered_components() {
+    let mut app = App::new();
+    app.add_plugins(EntityRelationMapperPlugin)
+        .register_db_component::<User>()
+        .register_db_component::<Seller>();
+    EntityRelationMapperPlugin::create_schema::<User>(
+        app.world.resource::<SqlxSqliteDatabaseResource>(),
+    )
+    .unwrap();
+
+    app.update();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registered_components_are_flushed() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&mut User>| {
+            block_on(async {
+                users.get_mut(&(1, request)).await.unwrap().name = "Bobby".to_string();
+                users
+                    .create(
+                        User {
+                            name: "Alice".to_string(),
+                        },
+                        request,
+                    )
+                    .await
+                    .unwrap();
+            })
+        });
+    let mut users = app.world.query::<(Entity, &User)>();
+    let bob = users
+        .iter(&app.world)
+        .find(|(_, user)| user.name == "Bobby")
+        .map(|(entity, _)| entity)
+        .unwrap();
+    app.world.entity_mut(bob).insert(Buyer {});
+
+    flush(&mut app, request);
+
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let rows = block_on(
+        sqlx::query_as::<_, (String, bool)>("SELECT name, buyer FROM users ORDER BY name")
+            .fetch_all(&pool),
+    )
+    .unwrap();
+    assert_eq!(
+        rows,
+        vec![("Alice".to_string(), false), ("Bobby".to_string(), true)]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removals_are_written_when_flushed_in_a_later_update() {
+    let mut app = setup_app();
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query("UPDATE users SET buyer = 1 WHERE id = 1").execute(&pool)).unwrap();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |users: DatabaseQuery<(&User, &Buyer)>| {
+            block_on(users.get(&(1, request))).unwrap();
+        });
+    let bob = app
+        .world
+        .query_filtered::<Entity, With<Buyer>>()
+        .single(&app.world);
+    app.world.entity_mut(bob).remove::<Buyer>();
+
+    // Bevy has forgotten the removal by the time the request is flushed
+    app.update();
+    app.update();
+    flush(&mut app, request);
+
+    let buyer: bool =
+        block_on(sqlx::query_scalar("SELECT buyer FROM users WHERE id = 1").fetch_one(&pool))
+            .unwrap();
+    assert!(!buyer);
+}
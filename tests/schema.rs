@@ -0,0 +1,305 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_erm::*;
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+use futures::executor::block_on;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+    pub nickname: Option<String>,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+pub struct Seller {}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "items"]
+pub struct MarketItem {
+    pub seller_id: DatabaseEntityId,
+    #[column = "item_name"]
+    pub name: String,
+    pub price: f64,
+    #[readonly]
+    pub listed: i64,
+    #[skip]
+    pub views: i64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "purchased_items"]
+pub struct PurchasedItem {
+    #[main_key]
+    pub item: i64,
+    #[main_key]
+    pub buyer: i64,
+    pub quantity: i64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "countries"]
+#[primary_key = "code"]
+#[key_type = "text"]
+pub struct Country {
+    pub name: String,
+}
+
+#[test]
+fn table_schema_describes_the_component_columns() {
+    assert_eq!(
+        MarketItem::table_schema(),
+        TableSchema {
+            name: "items".to_string(),
+            columns: vec![
+                ColumnSchema::new("id", "INTEGER", false),
+                ColumnSchema::new("seller_id", "INTEGER", false),
+                ColumnSchema::new("item_name", "TEXT", false),
+                ColumnSchema::new("price", "REAL", false),
+                ColumnSchema::new("listed", "INTEGER", true),
+            ],
+            primary_key: vec!["id".to_string()],
+            foreign_keys: vec![],
+        }
+    );
+
+    assert_eq!(
+        PurchasedItem::table_schema().create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS purchased_items (item INTEGER NOT NULL, buyer INTEGER NOT NULL, quantity INTEGER NOT NULL, PRIMARY KEY (item, buyer))"
+    );
+
+    assert_eq!(
+        Country::table_schema().create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS countries (code TEXT NOT NULL, name TEXT NOT NULL, PRIMARY KEY (code))"
+    );
+}
+
+#[test]
+fn components_in_the_same_table_are_merged() {
+    let tables = merge_table_schemas(<(User, Buyer, MarketItem, Seller)>::table_schemas());
+
+    assert_eq!(
+        tables
+            .iter()
+            .map(|table| table.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["users", "items"]
+    );
+    assert_eq!(
+        tables[0].create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS users (id INTEGER NOT NULL, name TEXT NOT NULL, nickname TEXT, buyer BOOLEAN NOT NULL DEFAULT 0, seller BOOLEAN NOT NULL DEFAULT 0, PRIMARY KEY (id))"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn create_schema_creates_tables_usable_by_the_components() {
+    let mut world = World::new();
+    world.init_resource::<SqlxSqliteDatabaseResource>();
+
+    let db = world.resource::<SqlxSqliteDatabaseResource>();
+    EntityRelationMapperPlugin::create_schema::<(User, Buyer, Seller, MarketItem, PurchasedItem)>(
+        db,
+    )
+    .unwrap();
+    // Creating the schema again leaves the existing tables alone
+    EntityRelationMapperPlugin::create_schema::<(User, Buyer, Seller)>(db).unwrap();
+
+    let request = db.start_new_transaction();
+    block_on(async {
+        let tr = db.get_transaction(request);
+        let mut guard = tr.lock().await;
+        let tr = guard.a.as_mut().unwrap();
+
+        sqlx::query("INSERT INTO users (id, name) VALUES (1, 'Bob')")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+        let (buyer, seller) =
+            sqlx::query_as::<_, (bool, bool)>("SELECT buyer, seller FROM users WHERE id = 1")
+                .fetch_one(&mut **tr)
+                .await
+                .unwrap();
+        assert!(!buyer);
+        assert!(!seller);
+
+        sqlx::query(
+            "INSERT INTO items (id, seller_id, item_name, price) VALUES (1, 1, 'corn', 1.5)",
+        )
+        .execute(&mut **tr)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO purchased_items (item, buyer, quantity) VALUES (1, 1, 2)")
+            .execute(&mut **tr)
+            .await
+            .unwrap();
+    });
+    db.commit_transaction(request);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schema_created_from_the_components_is_valid() {
+    let db = SqlxSqliteDatabaseResource::default();
+    EntityRelationMapperPlugin::create_schema::<(User, Buyer, Seller, MarketItem, PurchasedItem)>(
+        &db,
+    )
+    .unwrap();
+
+    let mut validation = SchemaValidation::default();
+    validation.register::<(User, Buyer, Seller, MarketItem, PurchasedItem)>();
+    validation.validate(&db).unwrap();
+}
+
+fn create_registered_tables(
+    db: Res<SqlxSqliteDatabaseResource>,
+    registered: Res<SchemaValidation>,
+) {
+    registered.create_tables(&db).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tables_of_the_registered_components_are_created() {
+    let mut app = App::new();
+    app.add_plugins(EntityRelationMapperPlugin)
+        .register_db_component::<User>()
+        .register_db_component::<Buyer>()
+        .register_db_component::<MarketItem>()
+        .add_systems(
+            PreStartup,
+            create_registered_tables
+                .after(run_migrations)
+                .before(validate_schema),
+        );
+    app.update();
+
+    let mut validation = SchemaValidation::default();
+    validation.register::<(User, Buyer, MarketItem)>();
+    validation
+        .validate(app.world.resource::<SqlxSqliteDatabaseResource>())
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schema_validation_reports_every_mismatch() {
+    let db = SqlxSqliteDatabaseResource::default();
+    block_on(
+        sqlx::query(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name BLOB, nickname, buyer INTEGER)",
+        )
+        .execute(&db.pool()),
+    )
+    .unwrap();
+
+    let mut validation = SchemaValidation::default();
+    validation.register::<(User, Buyer, Seller, MarketItem)>();
+    let Err(SchemaValidationError::Mismatches(mismatches)) = validation.validate(&db) else {
+        panic!("Expected the schema to be invalid");
+    };
+
+    assert_eq!(
+        mismatches,
+        vec![
+            SchemaMismatch::IncompatibleType {
+                table: "users".to_string(),
+                column: "name".to_string(),
+                expected: "TEXT".to_string(),
+                found: "BLOB".to_string(),
+            },
+            SchemaMismatch::MissingColumn {
+                table: "users".to_string(),
+                column: "seller".to_string(),
+            },
+            SchemaMismatch::MissingTable {
+                table: "items".to_string(),
+            },
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[should_panic(expected = "column users.seller does not exist")]
+async fn plugin_refuses_to_start_with_an_invalid_schema() {
+    let mut app = App::new();
+    app.add_plugins(EntityRelationMapperPlugin);
+    EntityRelationMapperPlugin::create_schema::<(User, Buyer)>(
+        app.world.resource::<SqlxSqliteDatabaseResource>(),
+    )
+    .unwrap();
+
+    let mut validation = SchemaValidation::default();
+    validation.register::<(User, Buyer, Seller)>();
+    app.insert_resource(validation);
+    app.update();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schema_diff_is_written_as_a_migration_that_fixes_the_schema() {
+    let db = SqlxSqliteDatabaseResource::default();
+    block_on(
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name BLOB, buyer BOOLEAN)")
+            .execute(&db.pool()),
+    )
+    .unwrap();
+
+    let mut components = SchemaValidation::default();
+    components.register::<(User, Buyer, Seller, PurchasedItem)>();
+    let diff = components.diff(&db).unwrap();
+
+    assert_eq!(
+        diff.to_sql(),
+        "-- TODO: column users.name is BLOB but the component stores TEXT\n\
+         ALTER TABLE users ADD COLUMN nickname TEXT;\n\
+         ALTER TABLE users ADD COLUMN seller BOOLEAN NOT NULL DEFAULT 0;\n\
+         CREATE TABLE IF NOT EXISTS purchased_items (item INTEGER NOT NULL, buyer INTEGER NOT NULL, quantity INTEGER NOT NULL, PRIMARY KEY (item, buyer));\n"
+    );
+
+    let dir = std::env::temp_dir().join(format!("bevy_erm_schema_diff_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("0001_create_users.sql"), "").unwrap();
+
+    let path = Migrations::write_migration(&dir, "Add sellers", &diff.to_sql()).unwrap();
+    assert_eq!(path, dir.join("0002_add_sellers.sql"));
+
+    let migrations = Migrations::from_dir(&dir).unwrap();
+    migrations.run(&db).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Only the type of the name column is left for the migration author to fix
+    let diff = components.diff(&db).unwrap();
+    assert!(diff.statements.is_empty());
+    assert_eq!(diff.unresolved.len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn migrations_are_written_for_the_registered_components() {
+    let mut app = App::new();
+    app.add_plugins(EntityRelationMapperPlugin)
+        .register_db_component::<User>()
+        .register_db_component::<Buyer>();
+    let db = app.world.resource::<SqlxSqliteDatabaseResource>();
+    block_on(sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY)").execute(&db.pool()))
+        .unwrap();
+
+    let dir = std::env::temp_dir().join(format!("bevy_erm_registered_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let path = EntityRelationMapperPlugin::write_schema_migration(&app, &dir, "Add names").unwrap();
+    assert_eq!(path, Some(dir.join("0001_add_names.sql")));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("0001_add_names.sql")).unwrap(),
+        "ALTER TABLE users ADD COLUMN name TEXT;\n\
+         ALTER TABLE users ADD COLUMN nickname TEXT;\n\
+         ALTER TABLE users ADD COLUMN buyer BOOLEAN NOT NULL DEFAULT 0;\n"
+    );
+
+    // The written migration is applied before diffing again
+    let path = EntityRelationMapperPlugin::write_schema_migration(&app, &dir, "Again").unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(path, None);
+}
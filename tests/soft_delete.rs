@@ -0,0 +1,213 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+#[soft_delete(column = "deleted_at")]
+pub struct User {
+    pub name: String,
+}
+
+// Markers and enums of the table are given its column so they leave out its deleted rows too
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "users"]
+#[soft_delete(column = "deleted_at")]
+pub struct Buyer {}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, DBQueryDerive)]
+#[table_name = "users"]
+#[soft_delete(column = "deleted_at")]
+pub enum Role {
+    Buyer,
+    Seller,
+}
+
+fn setup_app() -> App {
+    common::setup_app(
+        |app| {
+            app.register_db_component::<User>()
+                .register_db_component::<Buyer>()
+                .register_db_component::<Role>();
+        },
+        "INSERT INTO users (id, name, buyer, role, deleted_at) VALUES \
+         (1, 'Bob', 1, 'Buyer', NULL), (2, 'Alice', 0, NULL, NULL), \
+         (3, 'Carol', 1, 'Seller', '2024-01-01 00:00:00')",
+    )
+}
+
+fn names<CDQ>(app: &mut App, query: impl FnOnce(RequestId) -> CDQ) -> Vec<String>
+where
+    CDQ: CustomDatabaseQuery<SqlxSqliteDatabaseResource, User> + Send + Sync + 'static,
+{
+    let request = start_request(app);
+    let mut query = Some(query(request));
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            let mut names =
+                block_on(users.load_components::<&User, _>(request, query.take().unwrap()))
+                    .unwrap()
+                    .into_iter()
+                    .map(|user| user.name.clone())
+                    .collect::<Vec<_>>();
+            names.sort();
+            names
+        })
+}
+
+#[test]
+fn the_deleted_column_is_part_of_the_table_schema() {
+    assert_eq!(
+        User::table_schema().create_table_sql(),
+        "CREATE TABLE IF NOT EXISTS users (id INTEGER NOT NULL, name TEXT NOT NULL, deleted_at TEXT, \
+         PRIMARY KEY (id))"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn loaders_leave_out_deleted_rows() {
+    let mut app = setup_app();
+
+    assert_eq!(names(&mut app, UserQueryLoadAll), vec!["Alice", "Bob"]);
+    assert_eq!(
+        names(&mut app, |request| {
+            UserQueryLoadWhere(request, Filter::column("name").ne("Bob"))
+        }),
+        vec!["Alice"]
+    );
+
+    let request = start_request(&app);
+    let found = app
+        .world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            block_on(users.get(&(3, request))).is_ok()
+        });
+    assert!(!found);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn markers_and_enums_of_deleted_rows_are_not_loaded() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    let (buyers, roles, carol) = app.world.run_system_once(
+        move |buyer_query: DatabaseQuery<&Buyer>, role_query: DatabaseQuery<&Role>| {
+            let buyers = block_on(
+                buyer_query
+                    .load_components::<&DatabaseEntity, _>(request, BuyerQueryLoadAll(request)),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|db_entity| db_entity.id.clone())
+            .collect::<Vec<_>>();
+            let roles = block_on(
+                role_query.load_components::<&Role, _>(request, RoleQueryLoadAll(request)),
+            )
+            .unwrap()
+            .into_iter()
+            .copied()
+            .collect::<Vec<_>>();
+            let carol = (
+                block_on(buyer_query.get(&(3, request))).is_ok(),
+                block_on(role_query.get(&(3, request))).is_ok(),
+            );
+            (buyers, roles, carol)
+        },
+    );
+
+    assert_eq!(buyers, vec![1.into()]);
+    assert_eq!(roles, vec![Role::Buyer]);
+    assert_eq!(carol, (false, false));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn with_deleted_loads_deleted_rows() {
+    let mut app = setup_app();
+
+    assert_eq!(
+        names(&mut app, |request| UserQueryLoadAll(request).with_deleted()),
+        vec!["Alice", "Bob", "Carol"]
+    );
+    assert_eq!(
+        names(&mut app, |request| {
+            UserQueryLoadWhere(request, Filter::column("name").ne("Bob")).with_deleted()
+        }),
+        vec!["Alice", "Carol"]
+    );
+
+    let request = start_request(&app);
+    let name = app
+        .world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            block_on(users.get(&WithDeleted((3, request))))
+                .unwrap()
+                .name
+                .clone()
+        });
+    assert_eq!(name, "Carol");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deleting_keeps_the_row_with_a_timestamp() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            block_on(users.delete(&(1, request))).unwrap();
+        });
+    flush(&mut app, request);
+
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let rows: Vec<(i64, Option<String>)> =
+        block_on(sqlx::query_as("SELECT id, deleted_at FROM users ORDER BY id").fetch_all(&pool))
+            .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (1, Some(NOW_TEXT.to_string())),
+            (2, None),
+            (3, Some("2024-01-01 00:00:00".to_string()))
+        ]
+    );
+
+    let loaded = app
+        .world
+        .query_filtered::<Entity, With<DatabaseEntity>>()
+        .iter(&app.world)
+        .collect::<Vec<Entity>>();
+    for entity in loaded {
+        app.world.despawn(entity);
+    }
+    assert_eq!(names(&mut app, UserQueryLoadAll), vec!["Alice"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upserting_a_deleted_row_restores_it() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |users: DatabaseQuery<&User>| {
+            let user = User {
+                name: "Caroline".to_string(),
+            };
+            block_on(users.upsert(user, 3, request)).unwrap();
+        });
+    flush(&mut app, request);
+
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let row: (String, Option<String>) = block_on(
+        sqlx::query_as("SELECT name, deleted_at FROM users WHERE id = 3").fetch_one(&pool),
+    )
+    .unwrap();
+    assert_eq!(row, ("Caroline".to_string(), None));
+}
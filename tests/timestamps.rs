@@ -0,0 +1,124 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "posts"]
+pub struct Post {
+    pub title: String,
+    #[created_at]
+    pub created_at: String,
+    #[updated_at]
+    pub updated_at: Option<i64>,
+}
+
+fn setup_app() -> App {
+    common::setup_app(
+        |app| {
+            app.register_db_component::<Post>();
+        },
+        "INSERT INTO posts (id, title, created_at, updated_at) \
+         VALUES (1, 'Hello', '2020-01-01 00:00:00', NULL)",
+    )
+}
+
+fn posts(app: &App) -> Vec<(String, String, Option<i64>)> {
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(
+        sqlx::query_as("SELECT title, created_at, updated_at FROM posts ORDER BY id")
+            .fetch_all(&pool),
+    )
+    .unwrap()
+}
+
+#[test]
+fn timestamps_are_formatted_like_sqlite() {
+    let at = |secs| String::from_system_time(UNIX_EPOCH + Duration::from_secs(secs));
+    assert_eq!(at(0), "1970-01-01 00:00:00");
+    assert_eq!(at(951_782_400), "2000-02-29 00:00:00");
+    assert_eq!(at(NOW), NOW_TEXT);
+    assert_eq!(
+        Option::<i64>::from_system_time(UNIX_EPOCH + Duration::from_secs(NOW)),
+        Some(NOW as i64)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn inserting_sets_both_timestamps() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |posts: DatabaseQuery<&Post>| {
+            let post = Post {
+                title: "New".to_string(),
+                ..Default::default()
+            };
+            block_on(posts.create(post, request)).unwrap();
+        });
+    flush(&mut app, request);
+
+    // Created rows are given negative keys so come before the existing post
+    assert_eq!(
+        posts(&app)[0],
+        ("New".to_string(), NOW_TEXT.to_string(), Some(NOW as i64))
+    );
+    let mut in_memory = app.world.query::<&Post>();
+    assert_eq!(in_memory.single(&app.world).created_at, NOW_TEXT);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updating_only_sets_updated_at() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |posts: DatabaseQuery<&mut Post>| {
+            block_on(posts.get_mut(&(1, request))).unwrap().title = "Hello again".to_string();
+        });
+    flush(&mut app, request);
+
+    assert_eq!(
+        posts(&app),
+        vec![(
+            "Hello again".to_string(),
+            "2020-01-01 00:00:00".to_string(),
+            Some(NOW as i64)
+        )]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upserting_an_existing_row_keeps_created_at() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |posts: DatabaseQuery<&Post>| {
+            let post = Post {
+                title: "Replaced".to_string(),
+                ..Default::default()
+            };
+            block_on(posts.upsert(post, 1, request)).unwrap();
+        });
+    flush(&mut app, request);
+
+    assert_eq!(
+        posts(&app),
+        vec![(
+            "Replaced".to_string(),
+            "2020-01-01 00:00:00".to_string(),
+            Some(NOW as i64)
+        )]
+    );
+}
@@ -0,0 +1,11 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    #[column(display_name)]
+    pub name: String,
+}
+
+fn main() {}
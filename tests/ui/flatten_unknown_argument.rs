@@ -0,0 +1,16 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(DBEmbedded)]
+pub struct Money {
+    pub amount: i64,
+}
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = "invoices"]
+pub struct Invoice {
+    #[flatten(prefx = "total_")]
+    pub total: Money,
+}
+
+fn main() {}
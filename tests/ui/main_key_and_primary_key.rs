@@ -0,0 +1,13 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = "memberships"]
+#[primary_key = "id"]
+pub struct Membership {
+    #[main_key]
+    pub user_id: i64,
+    pub role: String,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[marker_table(key = "user_id")]
+pub struct Admin {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+pub struct User {
+    pub name: String,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User {
+    pub name: String,
+    #[skip = true]
+    pub views: i64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[marker_table(table = "admins", key = "user_id")]
+#[soft_delete(column = "deleted_at")]
+pub struct Admin {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = users]
+pub struct User {
+    pub name: String,
+}
+
+fn main() {}
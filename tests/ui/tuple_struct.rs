@@ -0,0 +1,8 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = "users"]
+pub struct User(String);
+
+fn main() {}
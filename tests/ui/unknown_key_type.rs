@@ -0,0 +1,11 @@
+use bevy_ecs::component::Component;
+use bevy_erm::*;
+
+#[derive(Component, DBQueryDerive)]
+#[table_name = "users"]
+#[key_type = "string"]
+pub struct User {
+    pub name: String,
+}
+
+fn main() {}
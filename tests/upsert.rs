@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+use bevy_erm::*;
+use futures::executor::block_on;
+
+use bevy_erm_core::database_query::{CustomDatabaseQuery, DatabaseTransaction};
+
+pub mod common;
+use common::*;
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "rates"]
+pub struct ExchangeRate {
+    pub currency: String,
+    pub rate: f64,
+}
+
+#[derive(Component, Debug, Default, Clone, DBQueryDerive)]
+#[table_name = "stock"]
+#[upsert]
+pub struct Stock {
+    #[main_key]
+    pub warehouse: i64,
+    #[main_key]
+    pub sku: i64,
+    pub quantity: i64,
+}
+
+fn setup_app() -> App {
+    common::setup_app(
+        |app| {
+            app.register_db_component::<ExchangeRate>()
+                .register_db_component::<Stock>();
+        },
+        "INSERT INTO rates (id, currency, rate) VALUES (1, 'EUR', 1.1); \
+         INSERT INTO stock (warehouse, sku, quantity) VALUES (1, 10, 5)",
+    )
+}
+
+fn rates(app: &App) -> Vec<(i64, String, f64)> {
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    block_on(sqlx::query_as("SELECT id, currency, rate FROM rates ORDER BY id").fetch_all(&pool))
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upsert_inserts_or_updates_without_loading() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    let rate = app
+        .world
+        .run_system_once(move |rates: DatabaseQuery<&ExchangeRate>| {
+            block_on(async {
+                for (id, currency, rate) in [(1, "EUR", 1.2), (2, "GBP", 1.3)] {
+                    let component = ExchangeRate {
+                        currency: currency.to_string(),
+                        rate,
+                    };
+                    rates.upsert(component, id, request).await.unwrap();
+                }
+                rates.get(&(1, request)).await.unwrap().rate
+            })
+        });
+    assert_eq!(rate, 1.2);
+
+    flush(&mut app, request);
+
+    assert_eq!(
+        rates(&app),
+        vec![(1, "EUR".to_string(), 1.2), (2, "GBP".to_string(), 1.3)]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn components_marked_upsert_update_rows_that_already_exist() {
+    let mut app = setup_app();
+    let request = start_request(&app);
+
+    app.world
+        .run_system_once(move |stock: DatabaseQuery<&Stock>| {
+            block_on(async {
+                for (sku, quantity) in [(10, 7), (11, 2)] {
+                    let component = Stock {
+                        warehouse: 1,
+                        sku,
+                        quantity,
+                    };
+                    stock.create(component, request).await.unwrap();
+                }
+            })
+        });
+
+    flush(&mut app, request);
+
+    let pool = app.world.resource::<SqlxSqliteDatabaseResource>().pool();
+    let rows: Vec<(i64, i64, i64)> = block_on(
+        sqlx::query_as("SELECT warehouse, sku, quantity FROM stock ORDER BY sku").fetch_all(&pool),
+    )
+    .unwrap();
+    assert_eq!(rows, vec![(1, 10, 7), (1, 11, 2)]);
+}